@@ -0,0 +1,496 @@
+use crate::{
+    Args, ColorChoice, Command, EntrySort, FolderArgs, ListCommand, Only, OutputFormat,
+    TimestampFormat, unescape_separator,
+};
+use clap::{ArgMatches, parser::ValueSource};
+use codep::{ControlChars, Editor, HistoryOrder, MatchMode, PathPattern, RecentOrder, SortKey};
+use regex::Regex;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Defaults for the command line arguments, read from `code-pick-recent.toml` next to the
+/// config root given on the command line or `$XDG_CONFIG_HOME/code-pick-recent/config.toml`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    config_root: Option<PathBuf>,
+    portable: Option<PathBuf>,
+    null_terminated: Option<bool>,
+    output_separator: Option<String>,
+    use_pango_markup: Option<bool>,
+    color: Option<ColorChoice>,
+    all: Option<bool>,
+    only: Option<Only>,
+    format: Option<OutputFormat>,
+    json_pretty: Option<bool>,
+    editor: Option<Editor>,
+    skip_missing: Option<bool>,
+    tilde: Option<bool>,
+    unique: Option<bool>,
+    sort: Option<EntrySort>,
+    reverse: Option<bool>,
+    relative_to: Option<PathBuf>,
+    basename_only: Option<bool>,
+    plain_remotes: Option<bool>,
+    template: Option<String>,
+    profile: Option<String>,
+    normalize: Option<bool>,
+    with_icon: Option<bool>,
+    icons: HashMap<String, String>,
+    nerd_icons: Option<bool>,
+    nerd_glyphs: HashMap<String, String>,
+    remote_labels: HashMap<String, String>,
+    field_separator: Option<String>,
+    windows_paths: Option<bool>,
+    control_chars: Option<ControlChars>,
+    home: Option<PathBuf>,
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+    #[serde(rename = "match")]
+    matches: Option<Vec<String>>,
+    match_mode: Option<MatchMode>,
+    filter_regex: Option<Vec<String>>,
+    exclude_regex: Option<Vec<String>>,
+    cache: Option<bool>,
+    recent: RecentConfig,
+    workspaces: FolderConfig,
+    history: HistoryConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RecentConfig {
+    with_files: Option<bool>,
+    with_dirs: Option<bool>,
+    all: Option<bool>,
+    order: Option<RecentOrder>,
+    max_age_days: Option<u32>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FolderConfig {
+    with_dirs: Option<bool>,
+    with_remotes: Option<bool>,
+    all: Option<bool>,
+    create_display_strings: Option<bool>,
+    max_age_days: Option<u32>,
+    limit: Option<usize>,
+    sort: Option<SortKey>,
+    reverse: Option<bool>,
+    dedupe: Option<bool>,
+    with_timestamp: Option<bool>,
+    timestamp_epoch: Option<bool>,
+    timestamp_format: Option<TimestampFormat>,
+    relative_time: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct HistoryConfig {
+    with_dirs: Option<bool>,
+    with_remotes: Option<bool>,
+    all: Option<bool>,
+    create_display_strings: Option<bool>,
+    max_age_days: Option<u32>,
+    limit: Option<usize>,
+    sort: Option<SortKey>,
+    reverse: Option<bool>,
+    dedupe: Option<bool>,
+    with_timestamp: Option<bool>,
+    timestamp_epoch: Option<bool>,
+    timestamp_format: Option<TimestampFormat>,
+    relative_time: Option<bool>,
+    with_files: Option<bool>,
+    order: Option<HistoryOrder>,
+    frecency_halflife_days: Option<f64>,
+}
+
+impl FolderConfig {
+    fn apply(self, args: &mut FolderArgs, matches: &ArgMatches) {
+        let FolderConfig {
+            with_dirs,
+            with_remotes,
+            all,
+            create_display_strings,
+            max_age_days,
+            limit,
+            sort,
+            reverse,
+            dedupe,
+            with_timestamp,
+            timestamp_epoch,
+            timestamp_format,
+            relative_time,
+        } = self;
+        merge(&mut args.with_dirs, with_dirs, matches, "with_dirs");
+        merge(
+            &mut args.with_remotes,
+            with_remotes,
+            matches,
+            "with_remotes",
+        );
+        merge(&mut args.all, all, matches, "all");
+        merge(
+            &mut args.create_display_strings,
+            create_display_strings,
+            matches,
+            "create_display_strings",
+        );
+        merge(
+            &mut args.max_age_days,
+            max_age_days.map(Some),
+            matches,
+            "max_age_days",
+        );
+        merge(&mut args.limit, limit.map(Some), matches, "limit");
+        merge(&mut args.sort, sort, matches, "sort");
+        merge(&mut args.reverse, reverse, matches, "reverse");
+        merge(&mut args.dedupe, dedupe, matches, "dedupe");
+        merge(
+            &mut args.with_timestamp,
+            with_timestamp,
+            matches,
+            "with_timestamp",
+        );
+        merge(
+            &mut args.timestamp_epoch,
+            timestamp_epoch,
+            matches,
+            "timestamp_epoch",
+        );
+        merge(
+            &mut args.timestamp_format,
+            timestamp_format.map(Some),
+            matches,
+            "timestamp_format",
+        );
+        merge(
+            &mut args.relative_time,
+            relative_time,
+            matches,
+            "relative_time",
+        );
+    }
+}
+
+pub fn get_default_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("code-pick-recent/config.toml"))
+}
+
+/// `code-pick-recent.toml` in the directory containing `config_root`.
+pub fn get_config_path_next_to(config_root: &Path) -> Option<PathBuf> {
+    let config_root = std::path::absolute(config_root).ok()?;
+    Some(config_root.parent()?.join("code-pick-recent.toml"))
+}
+
+impl Config {
+    /// Reads the config file next to `config_root` if there is one, the default one
+    /// otherwise.
+    pub fn load(config_root: Option<&Path>) -> anyhow::Result<Option<Config>> {
+        let next_to_config_root = config_root
+            .and_then(get_config_path_next_to)
+            .filter(|path| path.is_file());
+        let Some(path) = next_to_config_root.or_else(get_default_config_path) else {
+            return Ok(None);
+        };
+        if !fs::exists(&path)? {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path)?;
+        let config = toml::from_str(&content).map_err(|err| {
+            anyhow::anyhow!("Failed parsing config file {}! {err}", path.display())
+        })?;
+        Ok(Some(config))
+    }
+
+    /// Overwrites every argument in `args` that wasn't given on the command line or by an
+    /// environment variable.
+    pub fn apply(self, args: &mut Args, matches: &ArgMatches) -> anyhow::Result<()> {
+        let Config {
+            config_root,
+            portable,
+            null_terminated,
+            output_separator,
+            use_pango_markup,
+            color,
+            all,
+            only,
+            format,
+            json_pretty,
+            editor,
+            skip_missing,
+            tilde,
+            unique,
+            sort,
+            reverse,
+            relative_to,
+            basename_only,
+            plain_remotes,
+            template,
+            profile,
+            normalize,
+            with_icon,
+            icons,
+            nerd_icons,
+            nerd_glyphs,
+            remote_labels,
+            field_separator,
+            windows_paths,
+            control_chars,
+            home,
+            include,
+            exclude,
+            matches: match_strings,
+            match_mode,
+            filter_regex,
+            exclude_regex,
+            cache,
+            recent,
+            workspaces,
+            history,
+        } = self;
+        // A `--portable` on the command line beats a `config_root` from the file.
+        if args.portable.is_none() {
+            merge(
+                &mut args.config_root,
+                config_root.map(|config_root| vec![config_root]),
+                matches,
+                "config_root",
+            );
+        }
+        merge(&mut args.portable, portable.map(Some), matches, "portable");
+        // Either terminator on the command line beats both from the file.
+        if args.output_separator.is_none() && !args.null_terminated {
+            merge(
+                &mut args.null_terminated,
+                null_terminated,
+                matches,
+                "null_terminated",
+            );
+            merge(
+                &mut args.output_separator,
+                output_separator
+                    .map(|s| unescape_separator(&s).map_err(|err| anyhow::anyhow!("{err}!")))
+                    .transpose()?
+                    .map(Some),
+                matches,
+                "output_separator",
+            );
+        }
+        merge(
+            &mut args.use_pango_markup,
+            use_pango_markup,
+            matches,
+            "use_pango_markup",
+        );
+        merge(&mut args.color, color, matches, "color");
+        merge(&mut args.all, all, matches, "all");
+        merge(&mut args.only, only.map(Some), matches, "only");
+        merge(&mut args.format, format, matches, "format");
+        merge(&mut args.json_pretty, json_pretty, matches, "json_pretty");
+        merge(&mut args.editor, editor, matches, "editor");
+        merge(
+            &mut args.skip_missing,
+            skip_missing,
+            matches,
+            "skip_missing",
+        );
+        merge(&mut args.tilde, tilde, matches, "tilde");
+        merge(&mut args.unique, unique, matches, "unique");
+        merge(&mut args.sort, sort.map(Some), matches, "sort");
+        merge(&mut args.reverse, reverse, matches, "reverse");
+        merge(
+            &mut args.relative_to,
+            relative_to.map(Some),
+            matches,
+            "relative_to",
+        );
+        merge(
+            &mut args.basename_only,
+            basename_only,
+            matches,
+            "basename_only",
+        );
+        merge(
+            &mut args.plain_remotes,
+            plain_remotes,
+            matches,
+            "plain_remotes",
+        );
+        merge(&mut args.template, template.map(Some), matches, "template");
+        merge(&mut args.profile, profile.map(Some), matches, "profile");
+        merge(&mut args.normalize, normalize, matches, "normalize");
+        merge(&mut args.with_icon, with_icon, matches, "with_icon");
+        args.icons.extend(icons);
+        merge(&mut args.nerd_icons, nerd_icons, matches, "nerd_icons");
+        args.nerd_glyphs.extend(nerd_glyphs);
+        // Labels given on the command line come later and win.
+        args.remote_labels.splice(0..0, remote_labels);
+        merge(
+            &mut args.field_separator,
+            field_separator,
+            matches,
+            "field_separator",
+        );
+        merge(
+            &mut args.windows_paths,
+            windows_paths,
+            matches,
+            "windows_paths",
+        );
+        merge(
+            &mut args.control_chars,
+            control_chars,
+            matches,
+            "control_chars",
+        );
+        merge(&mut args.home, home.map(Some), matches, "home");
+        merge(
+            &mut args.include,
+            include.map(parse_patterns).transpose()?,
+            matches,
+            "include",
+        );
+        merge(
+            &mut args.exclude,
+            exclude.map(parse_patterns).transpose()?,
+            matches,
+            "exclude",
+        );
+        merge(&mut args.matches, match_strings, matches, "matches");
+        merge(&mut args.match_mode, match_mode, matches, "match_mode");
+        merge(
+            &mut args.filter_regex,
+            filter_regex.map(parse_regexes).transpose()?,
+            matches,
+            "filter_regex",
+        );
+        merge(
+            &mut args.exclude_regex,
+            exclude_regex.map(parse_regexes).transpose()?,
+            matches,
+            "exclude_regex",
+        );
+        merge(&mut args.cache, cache, matches, "cache");
+
+        let Some((_, mut matches)) = matches.subcommand() else {
+            return Ok(());
+        };
+        let command = match &mut args.command {
+            Some(Command::List(command)) => command,
+            // `count` takes the defaults of the subcommand it counts.
+            Some(Command::Count { command }) => {
+                let Some((_, counted_matches)) = matches.subcommand() else {
+                    return Ok(());
+                };
+                matches = counted_matches;
+                command
+            }
+            _ => return Ok(()),
+        };
+        match command {
+            ListCommand::Recent {
+                with_files,
+                with_dirs,
+                all,
+                order,
+                max_age_days,
+                limit,
+            } => {
+                merge(with_files, recent.with_files, matches, "with_files");
+                merge(with_dirs, recent.with_dirs, matches, "with_dirs");
+                merge(all, recent.all, matches, "all");
+                merge(order, recent.order, matches, "order");
+                merge(
+                    max_age_days,
+                    recent.max_age_days.map(Some),
+                    matches,
+                    "max_age_days",
+                );
+                merge(limit, recent.limit.map(Some), matches, "limit");
+            }
+            ListCommand::Workspaces { folder } => workspaces.apply(folder, matches),
+            ListCommand::History {
+                folder,
+                with_files,
+                order,
+                frecency_halflife_days,
+                ..
+            } => {
+                let HistoryConfig {
+                    with_dirs,
+                    with_remotes,
+                    all,
+                    create_display_strings,
+                    max_age_days,
+                    limit,
+                    sort,
+                    reverse,
+                    dedupe,
+                    with_timestamp,
+                    timestamp_epoch,
+                    timestamp_format,
+                    relative_time,
+                    with_files: history_with_files,
+                    order: history_order,
+                    frecency_halflife_days: history_frecency_halflife_days,
+                } = history;
+                let folder_config = FolderConfig {
+                    with_dirs,
+                    with_remotes,
+                    all,
+                    create_display_strings,
+                    max_age_days,
+                    limit,
+                    sort,
+                    reverse,
+                    dedupe,
+                    with_timestamp,
+                    timestamp_epoch,
+                    timestamp_format,
+                    relative_time,
+                };
+                folder_config.apply(folder, matches);
+                merge(with_files, history_with_files, matches, "with_files");
+                merge(order, history_order, matches, "order");
+                merge(
+                    frecency_halflife_days,
+                    history_frecency_halflife_days,
+                    matches,
+                    "frecency_halflife_days",
+                );
+            }
+            ListCommand::All { .. } => {}
+        }
+        Ok(())
+    }
+}
+
+fn merge<T>(target: &mut T, value: Option<T>, matches: &ArgMatches, id: &str) {
+    let Some(value) = value else {
+        return;
+    };
+    if !matches!(
+        matches.value_source(id),
+        Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+    ) {
+        *target = value;
+    }
+}
+
+fn parse_regexes(regexes: Vec<String>) -> anyhow::Result<Vec<Regex>> {
+    regexes
+        .iter()
+        .map(|r| Regex::new(r).map_err(|err| anyhow::anyhow!("Invalid regex `{r}`! {err}")))
+        .collect()
+}
+
+fn parse_patterns(patterns: Vec<String>) -> anyhow::Result<Vec<PathPattern>> {
+    patterns.iter().map(|p| p.parse()).collect()
+}
@@ -0,0 +1,2402 @@
+//! Reads the recently opened files, folders and remotes from the storage of VS Code and
+//! its forks. The `codep` binary is a thin CLI around these functions.
+//!
+//! ```no_run
+//! use codep::{Editor, Filter, RecentOptions, get_default_config_root, get_user_dir};
+//!
+//! let user_dir = get_user_dir(&get_default_config_root(Editor::default())?);
+//! let options = RecentOptions {
+//!     with_dirs: true,
+//!     ..Default::default()
+//! };
+//! let mut records = Vec::new();
+//! codep::recent_entries(&user_dir, &options, &Filter::default(), &mut records)?;
+//! for record in records {
+//!     println!("{:?} {}", record.kind, record.path);
+//! }
+//! # anyhow::Ok(())
+//! ```
+
+use anyhow::anyhow;
+use clap::ValueEnum;
+use globset::{Glob, GlobMatcher};
+use log::{Level, debug, log_enabled, trace, warn};
+use regex::Regex;
+#[cfg(feature = "sqlite")]
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use sonic_rs::{JsonContainerTrait, JsonValueTrait};
+use std::{
+    borrow::Cow,
+    cmp::{Ordering, Reverse},
+    collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet},
+    fmt::{Debug, Display},
+    fs::{self, DirEntry, File},
+    io::Read,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+    sync::atomic::{self, AtomicUsize},
+    time::{Duration, SystemTime},
+};
+
+/// The config root in the platform's config directory, or in `$XDG_CONFIG_HOME` or
+/// `~/.config`, where some forks keep it on macOS. The first one with a `storage.json` wins,
+/// without any the platform's one is used.
+pub fn get_default_config_root(editor: Editor) -> anyhow::Result<PathBuf> {
+    let xdg_config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute());
+    let mut candidates: Vec<PathBuf> = Vec::new();
+    for base in [
+        dirs::config_dir(),
+        xdg_config_home,
+        dirs::home_dir().map(|home| home.join(".config")),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        let config_root = base.join(get_config_dir_name_from_editor(editor));
+        if !candidates.contains(&config_root) {
+            candidates.push(config_root);
+        }
+    }
+    let config_root = candidates
+        .iter()
+        .find(|config_root| get_storage_json_path(&get_user_dir(config_root)).exists())
+        .or(candidates.first())
+        .ok_or_else(|| anyhow!("Couldn't determine the config directory, pass `--config-root`!"))?
+        .clone();
+    debug!("Using config root {}", config_root.display());
+    Ok(config_root)
+}
+
+/// Config root of a portable install, which keeps everything in `<install_dir>/data`.
+pub fn get_portable_config_root(install_dir: &Path) -> anyhow::Result<PathBuf> {
+    let data_dir = install_dir.join("data");
+    if !data_dir.is_dir() {
+        return Err(anyhow!(
+            "No `data` directory in {}! Is it a portable install?",
+            install_dir.display()
+        ));
+    }
+    Ok(data_dir.join("user-data"))
+}
+
+pub fn get_config_dir_name_from_editor(editor: Editor) -> &'static str {
+    match editor {
+        Editor::Code => "Code",
+        Editor::CodeInsiders => "Code - Insiders",
+        Editor::Codium => "VSCodium",
+        Editor::Cursor => "Cursor",
+        Editor::Oss => "Code - OSS",
+    }
+}
+
+pub fn get_executable_name_from_editor(editor: Editor) -> &'static str {
+    match editor {
+        Editor::Code => "code",
+        Editor::CodeInsiders => "code-insiders",
+        Editor::Codium => "codium",
+        Editor::Cursor => "cursor",
+        Editor::Oss => "code-oss",
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Editor {
+    #[default]
+    Code,
+    CodeInsiders,
+    Codium,
+    Cursor,
+    Oss,
+}
+
+#[derive(Debug, Clone, Default, ValueEnum, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RecentOrder {
+    #[default]
+    Unchanged,
+    FilesFirst,
+    DirsFirst,
+    /// Newest modification time of the local path first. Paths that can't be inspected
+    /// are placed last, in their original order.
+    Mtime,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RecentOptions {
+    pub with_files: bool,
+    pub with_dirs: bool,
+    pub order: RecentOrder,
+    /// Compared against the modification time of local paths. Paths that can't be
+    /// inspected (e.g. remotes) are kept.
+    pub max_age_days: Option<u32>,
+    pub limit: Option<usize>,
+    pub windows_paths: bool,
+    pub control_chars: ControlChars,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct WorkspacesOptions {
+    pub with_dirs: bool,
+    pub with_remotes: bool,
+    pub create_display_strings: bool,
+    pub max_age_days: Option<u32>,
+    /// Combined with `max_age_days`, the later bound wins.
+    pub after: Option<SystemTime>,
+    pub before: Option<SystemTime>,
+    /// With a sort other than [`SortKey::Mtime`] or `reverse` this is applied after
+    /// sorting, so every entry is read.
+    pub limit: Option<usize>,
+    /// Caps the number of returned entries. Unlike `limit`, which caps the number of
+    /// directories read, reading stops once this many entries were found, as far as the
+    /// order allows.
+    pub first_only: Option<usize>,
+    pub sort: SortKey,
+    pub reverse: bool,
+    pub windows_paths: bool,
+    pub control_chars: ControlChars,
+    /// Keep entries with empty or placeholder paths.
+    pub keep_placeholders: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortKey {
+    /// Newest first.
+    #[default]
+    Mtime,
+    /// By the last path segment.
+    Name,
+    Path,
+}
+
+pub const DEFAULT_FRECENCY_HALFLIFE_DAYS: f64 = 7.0;
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HistoryOrder {
+    #[default]
+    Mtime,
+    /// Ranks by `frequency * recency_weight`, where the recency weight halves every
+    /// `frecency_halflife_days`. Entries are merged by resource.
+    Frecency,
+}
+
+#[derive(Clone, Debug)]
+pub struct HistoryOptions {
+    /// The local history only tracks files.
+    pub with_files: bool,
+    pub with_remotes: bool,
+    pub create_display_strings: bool,
+    pub max_age_days: Option<u32>,
+    /// Combined with `max_age_days`, the later bound wins.
+    pub after: Option<SystemTime>,
+    pub before: Option<SystemTime>,
+    /// With [`HistoryOrder::Frecency`] this is applied after ranking, so every entry is read.
+    pub limit: Option<usize>,
+    /// Caps the number of returned entries. Unlike `limit`, which caps the number of
+    /// directories read, reading stops once this many entries were found, as far as the
+    /// order allows.
+    pub first_only: Option<usize>,
+    pub order: HistoryOrder,
+    pub frecency_halflife_days: f64,
+    /// Keep only the newest entry of each resource. Ranking by frecency merges them anyway.
+    pub dedupe: bool,
+    /// Applied after `order`. [`SortKey::Mtime`] keeps the order.
+    pub sort: SortKey,
+    pub reverse: bool,
+    pub windows_paths: bool,
+    pub control_chars: ControlChars,
+    /// Keep entries with empty or placeholder paths.
+    pub keep_placeholders: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct AllOptions {
+    pub with_files: bool,
+    pub with_dirs: bool,
+    pub with_remotes: bool,
+    pub create_display_strings: bool,
+    pub max_age_days: Option<u32>,
+    /// Bounds like [`WorkspacesOptions::after`]. Recent entries are only kept with a
+    /// timestamp within them, which needs `max_age_days`.
+    pub after: Option<SystemTime>,
+    pub before: Option<SystemTime>,
+    /// Applied after merging, so every source is read.
+    pub limit: Option<usize>,
+    pub windows_paths: bool,
+    pub control_chars: ControlChars,
+    pub keep_placeholders: bool,
+}
+
+impl Default for HistoryOptions {
+    fn default() -> Self {
+        Self {
+            with_files: false,
+            with_remotes: false,
+            create_display_strings: false,
+            max_age_days: None,
+            after: None,
+            before: None,
+            limit: None,
+            first_only: None,
+            order: HistoryOrder::default(),
+            frecency_halflife_days: DEFAULT_FRECENCY_HALFLIFE_DAYS,
+            dedupe: true,
+            sort: SortKey::default(),
+            reverse: false,
+            windows_paths: false,
+            control_chars: ControlChars::default(),
+            keep_placeholders: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    /// Drop local entries whose path doesn't exist anymore. Remotes are always kept.
+    pub skip_missing: bool,
+    pub include: Vec<PathPattern>,
+    pub exclude: Vec<PathPattern>,
+    /// Plain strings, of which the path has to match any, see `match_mode`.
+    pub matches: Vec<String>,
+    pub match_mode: MatchMode,
+    /// Regexes of which the path has to match all.
+    pub required: Vec<Regex>,
+}
+
+impl Filter {
+    fn has_patterns(&self) -> bool {
+        !self.include.is_empty()
+            || !self.exclude.is_empty()
+            || !self.matches.is_empty()
+            || !self.required.is_empty()
+    }
+
+    /// An entry is kept if it matches any include (or there are none), any of `matches`
+    /// (or there are none), all of `required` and no exclude.
+    fn matches_patterns(&self, path: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| p.is_match(path)))
+            && (self.matches.is_empty()
+                || self
+                    .matches
+                    .iter()
+                    .any(|s| self.match_mode.is_match(s, path)))
+            && self.required.iter().all(|r| r.is_match(path))
+            && !self.exclude.iter().any(|p| p.is_match(path))
+    }
+
+    /// Matches the patterns against `path` as shown, but checks whether `fs_path` exists,
+    /// which differs with `windows_paths` or control characters.
+    fn keep_local_path(&self, path: &str, fs_path: &str) -> bool {
+        if !self.matches_patterns(path) {
+            return false;
+        }
+        if self.skip_missing && !fs::exists(fs_path).unwrap_or(true) {
+            debug!("Skipping missing `{path}`");
+            return false;
+        }
+        true
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MatchMode {
+    #[default]
+    Contains,
+    Prefix,
+    Exact,
+}
+
+impl MatchMode {
+    fn is_match(self, s: &str, path: &str) -> bool {
+        match self {
+            MatchMode::Contains => path.contains(s),
+            MatchMode::Prefix => path.starts_with(s),
+            MatchMode::Exact => path == s,
+        }
+    }
+}
+
+/// A glob, or a regex when wrapped in slashes (`/.../`). Globs starting with `~/` need
+/// [`PathPattern::expand_home`] to match.
+#[derive(Clone, Debug)]
+pub enum PathPattern {
+    Glob(GlobMatcher),
+    Regex(Regex),
+}
+
+impl PathPattern {
+    pub fn is_match(&self, path: &str) -> bool {
+        match self {
+            PathPattern::Glob(glob) => glob.is_match(path),
+            PathPattern::Regex(regex) => regex.is_match(path),
+        }
+    }
+
+    /// Resolves a leading `~/` of globs against `home`.
+    pub fn expand_home(self, home: &Path) -> anyhow::Result<Self> {
+        let PathPattern::Glob(glob) = &self else {
+            return Ok(self);
+        };
+        let Some(rest) = glob.glob().glob().strip_prefix("~/") else {
+            return Ok(self);
+        };
+        let glob = home.join(rest).to_string_lossy().into_owned();
+        Ok(PathPattern::Glob(Glob::new(&glob)?.compile_matcher()))
+    }
+}
+
+impl FromStr for PathPattern {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(regex) = s
+            .strip_prefix('/')
+            .and_then(|s| s.strip_suffix('/'))
+            .filter(|s| !s.is_empty())
+        {
+            return Ok(PathPattern::Regex(Regex::new(regex)?));
+        }
+        Ok(PathPattern::Glob(Glob::new(s)?.compile_matcher()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RecordKind {
+    File,
+    Dir,
+    Remote,
+}
+
+impl RecordKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RecordKind::File => "file",
+            RecordKind::Dir => "dir",
+            RecordKind::Remote => "remote",
+        }
+    }
+}
+
+/// Which collector found a [`Record`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RecordSource {
+    Recent,
+    Workspace,
+    History,
+}
+
+impl RecordSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RecordSource::Recent => "recent",
+            RecordSource::Workspace => "workspace",
+            RecordSource::History => "history",
+        }
+    }
+}
+
+/// A single entry found in the editor's storage.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Record {
+    /// The value to hand to the editor: a local path for recent entries, a decoded
+    /// uri for workspaces and history.
+    pub path: String,
+    pub kind: RecordKind,
+    pub remote_type: Option<String>,
+    pub display: Option<DisplayInfo>,
+    pub last_modified_at: Option<SystemTime>,
+    pub source: RecordSource,
+    /// The `.code-workspace` file listing the folder.
+    pub workspace: Option<PathBuf>,
+}
+
+/// Receives collected entries one at a time, like the printer of the CLI. A `Vec` just
+/// keeps them, [`Dedupe`] and [`FirstOnly`] filter them for another sink.
+pub trait Sink {
+    fn emit(&mut self, record: Record) -> anyhow::Result<()>;
+
+    fn emit_all(&mut self, records: impl IntoIterator<Item = Record>) -> anyhow::Result<()>
+    where
+        Self: Sized,
+    {
+        records.into_iter().try_for_each(|record| self.emit(record))
+    }
+
+    /// Called once after the last entry, e.g. to print a summary.
+    fn finish(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+impl Sink for Vec<Record> {
+    fn emit(&mut self, record: Record) -> anyhow::Result<()> {
+        self.push(record);
+        Ok(())
+    }
+}
+
+impl<S: Sink + ?Sized> Sink for Box<S> {
+    fn emit(&mut self, record: Record) -> anyhow::Result<()> {
+        (**self).emit(record)
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        (**self).finish()
+    }
+}
+
+/// Passes on entries whose normalized path wasn't passed on before.
+pub struct Dedupe<S> {
+    inner: S,
+    seen: HashSet<String>,
+}
+
+impl<S> Dedupe<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            seen: HashSet::new(),
+        }
+    }
+}
+
+impl<S: Sink> Sink for Dedupe<S> {
+    fn emit(&mut self, record: Record) -> anyhow::Result<()> {
+        if !self.seen.insert(normalize_uri(&record.path).into_owned()) {
+            return Ok(());
+        }
+        self.inner.emit(record)
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.inner.finish()
+    }
+}
+
+/// Passes on the first `n` entries and drops the rest.
+pub struct FirstOnly<S> {
+    inner: S,
+    remaining: usize,
+}
+
+impl<S> FirstOnly<S> {
+    pub fn new(inner: S, n: usize) -> Self {
+        Self {
+            inner,
+            remaining: n,
+        }
+    }
+}
+
+impl<S: Sink> Sink for FirstOnly<S> {
+    fn emit(&mut self, record: Record) -> anyhow::Result<()> {
+        if self.remaining == 0 {
+            return Ok(());
+        }
+        self.remaining -= 1;
+        self.inner.emit(record)
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.inner.finish()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DisplayInfo {
+    pub val: String,
+    pub hint: Option<DisplayInfoHint>,
+}
+
+impl Display for DisplayInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let DisplayInfo { val, hint } = self;
+        f.write_str(val)?;
+        if let Some(hint) = hint {
+            Display::fmt(hint, f)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DisplayInfoHint {
+    pub remote_type: String,
+    pub addition: Option<String>,
+}
+
+impl Display for DisplayInfoHint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let DisplayInfoHint {
+            remote_type,
+            addition,
+        } = self;
+        f.write_fmt(format_args!(" ({remote_type}"))?;
+        if let Some(addition) = addition {
+            f.write_fmt(format_args!("|{addition}"))?;
+        }
+        f.write_fmt(format_args!(")"))?;
+        Ok(())
+    }
+}
+
+/// The `User` directory of the default profile. The collectors read everything relative
+/// to it.
+pub fn get_user_dir(config_root: &Path) -> PathBuf {
+    config_root.join("User")
+}
+
+/// Recently opened list of newer versions, preferred over [`get_storage_json_path`].
+pub fn get_state_db_path(user_dir: &Path) -> PathBuf {
+    user_dir.join("globalStorage/state.vscdb")
+}
+
+pub fn get_storage_json_path(user_dir: &Path) -> PathBuf {
+    user_dir.join("globalStorage/storage.json")
+}
+
+pub fn get_workspace_storage_path(user_dir: &Path) -> PathBuf {
+    user_dir.join("workspaceStorage")
+}
+
+pub fn get_history_path(user_dir: &Path) -> PathBuf {
+    user_dir.join("History")
+}
+
+#[derive(Clone, Debug)]
+pub struct Profile {
+    pub name: String,
+    /// `User` directory of the profile.
+    pub user_dir: PathBuf,
+}
+
+/// Reads the profiles from `userDataProfiles` in `storage.json`.
+pub fn profiles(config_root: &Path) -> anyhow::Result<Vec<Profile>> {
+    let user_dir = get_user_dir(config_root);
+    let storage_path = get_storage_json_path(&user_dir);
+    let content = fs::read(&storage_path)
+        .map_err(|err| anyhow!("Failed reading {}! {err}", storage_path.display()))?;
+    let value: sonic_rs::Value = sonic_rs::from_slice(&content)
+        .map_err(|err| anyhow!("Failed parsing {}! {err}", storage_path.display()))?;
+    let Some(profiles) = value.get("userDataProfiles").and_then(|v| v.as_array()) else {
+        return Ok(Vec::new());
+    };
+    let profiles = profiles
+        .iter()
+        .filter_map(|profile| {
+            let name = profile.get("name")?.as_str()?;
+            let location = profile.get("location")?.as_str()?;
+            let user_dir = match location.strip_prefix("file://") {
+                Some(path) => PathBuf::from(
+                    local_path_from_uri_path(&urlencoding::decode(path).ok()?, false).as_ref(),
+                ),
+                None => user_dir.join("profiles").join(location),
+            };
+            Some(Profile {
+                name: name.to_owned(),
+                user_dir,
+            })
+        })
+        .collect();
+    Ok(profiles)
+}
+
+/// The `User` directory of the profile called `name`.
+pub fn get_profile_user_dir(config_root: &Path, name: &str) -> anyhow::Result<PathBuf> {
+    let profiles = profiles(config_root)?;
+    if let Some(profile) = profiles.iter().find(|profile| profile.name == name) {
+        return Ok(profile.user_dir.clone());
+    }
+    let names = profiles
+        .iter()
+        .map(|profile| profile.name.as_str())
+        .collect::<Vec<_>>();
+    Err(anyhow!(
+        "Unknown profile `{name}`! Available: {}",
+        names.join(", ")
+    ))
+}
+
+/// Reads the recently opened files and folders from `state.vscdb` if it exists, otherwise
+/// from the `history.recentlyOpenedPathsList` or the menubar data in `storage.json`, falling
+/// back to `openedPathsList` and `backupWorkspaces`.
+pub fn recent_entries(
+    user_dir: &Path,
+    options: &RecentOptions,
+    filter: &Filter,
+    sink: &mut impl Sink,
+) -> anyhow::Result<()> {
+    sink.emit_all(read_recent_entries(user_dir, options, filter)?)
+}
+
+fn read_recent_entries(
+    user_dir: &Path,
+    options: &RecentOptions,
+    filter: &Filter,
+) -> anyhow::Result<Vec<Record>> {
+    let RecentOptions {
+        with_files,
+        with_dirs,
+        order,
+        max_age_days,
+        limit,
+        windows_paths,
+        control_chars,
+    } = options;
+    let (with_files, with_dirs) = (*with_files, *with_dirs);
+
+    let min_system_time = max_age_days
+        .map(get_min_system_time_from_max_age_days)
+        .transpose()?;
+
+    let state_db_path = get_state_db_path(user_dir);
+    // Nothing was opened since the list was last written.
+    if let Some(min_system_time) = min_system_time {
+        let written_at = [&state_db_path, &get_storage_json_path(user_dir)]
+            .into_iter()
+            .filter_map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+            .max();
+        if written_at.is_some_and(|t| t < min_system_time) {
+            return Ok(Vec::new());
+        }
+    }
+    let recently_opened = if fs::exists(&state_db_path)? {
+        // A locked or corrupt database shouldn't hide the entries of `storage.json`.
+        read_recently_opened_from_state_db(&state_db_path).unwrap_or_else(|err| {
+            warn!(
+                "Failed reading {}, falling back to storage.json! {err}",
+                state_db_path.display()
+            );
+            None
+        })
+    } else {
+        None
+    };
+    let value: sonic_rs::Value;
+    let uris = match &recently_opened {
+        Some(recently_opened) => {
+            let mut uris = Vec::new();
+            push_opened_paths_list_uris(recently_opened, &mut uris);
+            trace!("{} entries from {}", uris.len(), state_db_path.display());
+            recent_entries_from_uris(uris, with_files, with_dirs)
+        }
+        None => {
+            let storage_path = get_storage_json_path(user_dir);
+            trace!("Reading {}", storage_path.display());
+            let content = fs::read(&storage_path)
+                .map_err(|err| anyhow!("Failed reading {}! {err}", storage_path.display()))?;
+            value = match sonic_rs::from_slice(&content) {
+                Ok(value) => value,
+                Err(err) => {
+                    warn!(
+                        "Couldn't parse {}, skipping recent entries! {err}",
+                        storage_path.display()
+                    );
+                    return Ok(Vec::new());
+                }
+            };
+            // Only filled in once the File menu was rendered, so the list is preferred.
+            let entries =
+                if let Some(recently_opened) = value.get("history.recentlyOpenedPathsList") {
+                    let mut uris = Vec::new();
+                    push_opened_paths_list_uris(recently_opened, &mut uris);
+                    recent_entries_from_uris(uris, with_files, with_dirs)
+                } else {
+                    match recent_entries_from_menubar(&value, with_files, with_dirs) {
+                        Ok(entries) if !entries.is_empty() => entries,
+                        menubar => {
+                            match recent_entries_from_opened_paths(&value, with_files, with_dirs) {
+                                Some(entries) => entries,
+                                None => menubar?,
+                            }
+                        }
+                    }
+                };
+            trace!("{} entries from {}", entries.len(), storage_path.display());
+            entries
+        }
+    }
+    .into_iter();
+    let uris: Box<dyn Iterator<Item = _>> = match order {
+        RecentOrder::Unchanged | RecentOrder::Mtime => Box::new(uris),
+        RecentOrder::FilesFirst | RecentOrder::DirsFirst => {
+            let (first, second): (Vec<_>, Vec<_>) = uris.partition(|e| {
+                // want_file xnor is_file
+                !((*order == RecentOrder::FilesFirst) ^ (e.t == RecentEntryType::File))
+            });
+            Box::new(first.into_iter().chain(second))
+        }
+    };
+    let limit = limit.unwrap_or(usize::MAX);
+    let by_mtime = *order == RecentOrder::Mtime;
+    let read_limit = if by_mtime { usize::MAX } else { limit };
+    let mut records = Vec::new();
+    for RecentEntry { t, val } in uris {
+        if records.len() >= read_limit {
+            break;
+        }
+        let Ok(val) = urlencoding::decode(val).inspect_err(|err| debug!("{err}")) else {
+            continue;
+        };
+        let kind = match t {
+            RecentEntryType::File => RecordKind::File,
+            RecentEntryType::Dir => RecordKind::Dir,
+        };
+        let fs_path = local_path_from_uri_path(val.trim(), false);
+        let path = control_chars
+            .apply(&local_path_from_uri_path(val.trim(), *windows_paths))
+            .into_owned();
+        if !filter.keep_local_path(&path, &fs_path) {
+            continue;
+        }
+        let mut last_modified_at = None;
+        if min_system_time.is_some() || by_mtime {
+            last_modified_at = get_last_modified_at_from_path(fs_path.as_ref());
+        }
+        if let Some(min_system_time) = min_system_time {
+            if last_modified_at.is_some_and(|t| t < min_system_time) {
+                continue;
+            }
+        }
+        records.push(Record {
+            path,
+            kind,
+            remote_type: None,
+            display: None,
+            last_modified_at,
+            source: RecordSource::Recent,
+            workspace: None,
+        });
+    }
+    if by_mtime {
+        records.sort_by(|r1, r2| cmp_newest_first(r1.last_modified_at, r2.last_modified_at));
+        records.truncate(limit);
+    }
+    Ok(records)
+}
+
+fn recent_entries_from_menubar(
+    value: &sonic_rs::Value,
+    with_files: bool,
+    with_dirs: bool,
+) -> anyhow::Result<Vec<RecentEntry<'_>>> {
+    let items = value
+        .as_object_get_path_result(&["lastKnownMenubarData", "menus", "File", "items"])?
+        .as_array()
+        .ok_or_else(|| anyhow!("Failed using field in json as an array!"))?;
+    let recent = items
+        .iter()
+        .find(|item| {
+            let Ok(id) = item.as_object_get_result("id") else {
+                return false;
+            };
+            let Some(id) = id.as_str() else {
+                return false;
+            };
+            id == "submenuitem.MenubarRecentMenu"
+        })
+        .ok_or_else(|| anyhow!("Didn't find menubar!"))?;
+    let entries = recent
+        .as_object_get_path_result(&["submenu", "items"])?
+        .as_array()
+        .ok_or_else(|| anyhow!("Failed using field in json as an object!"))?
+        .iter()
+        .filter_map(move |item| {
+            let id = item.as_object_get_result("id").ok()?.as_str()?;
+            let keep_id =
+                with_files && id == "openRecentFile" || with_dirs && id == "openRecentFolder";
+            if !keep_id {
+                return None;
+            }
+            let is_enabled = item.get("enabled").and_then(|s| s.as_bool())?;
+            if !is_enabled {
+                return None;
+            }
+            let val = item
+                .as_object_get_result("uri")
+                .ok()?
+                .as_object_get_result("path")
+                .ok()?
+                .as_str()?;
+            let t = match id {
+                "openRecentFile" => RecentEntryType::File,
+                "openRecentFolder" => RecentEntryType::Dir,
+                _ => {
+                    debug!("Unsupported entry type id!");
+                    return None;
+                }
+            };
+            Some(RecentEntry { t, val })
+        })
+        .collect();
+    Ok(entries)
+}
+
+/// Reads the local entries of `openedPathsList` and `backupWorkspaces`, which some versions
+/// keep in `storage.json`. Returns `None` if neither exists.
+fn recent_entries_from_opened_paths(
+    value: &sonic_rs::Value,
+    with_files: bool,
+    with_dirs: bool,
+) -> Option<Vec<RecentEntry<'_>>> {
+    let opened_paths = value.get("openedPathsList");
+    let backup_workspaces = value.get("backupWorkspaces");
+    if opened_paths.is_none() && backup_workspaces.is_none() {
+        return None;
+    }
+
+    let mut uris = Vec::new();
+    if let Some(opened_paths) = opened_paths {
+        push_opened_paths_list_uris(opened_paths, &mut uris);
+    }
+    if let Some(backup_workspaces) = backup_workspaces {
+        let folders = backup_workspaces.get("folders").and_then(|v| v.as_array());
+        for folder in folders.into_iter().flat_map(|a| a.iter()) {
+            if let Some(uri) = folder.get("folderUri").and_then(|v| v.as_str()) {
+                uris.push((RecentEntryType::Dir, uri));
+            }
+        }
+        let folders = backup_workspaces
+            .get("folderURIWorkspaces")
+            .and_then(|v| v.as_array());
+        for folder in folders.into_iter().flat_map(|a| a.iter()) {
+            if let Some(uri) = folder.as_str() {
+                uris.push((RecentEntryType::Dir, uri));
+            }
+        }
+    }
+
+    Some(recent_entries_from_uris(uris, with_files, with_dirs))
+}
+
+/// Reads the `history.recentlyOpenedPathsList` of the `state.vscdb` database, which replaced
+/// the data in `storage.json` in newer versions.
+#[cfg(feature = "sqlite")]
+fn read_recently_opened_from_state_db(path: &Path) -> anyhow::Result<Option<sonic_rs::Value>> {
+    trace!("Reading {}", path.display());
+    let connection = rusqlite::Connection::open_with_flags(
+        path,
+        rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY | rusqlite::OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    let value = connection
+        .query_row(
+            "SELECT value FROM ItemTable WHERE key = 'history.recentlyOpenedPathsList'",
+            [],
+            |row| Ok(row.get_ref(0)?.as_bytes()?.to_vec()),
+        )
+        .optional()?;
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    match sonic_rs::from_slice(&value) {
+        Ok(value) => Ok(Some(value)),
+        Err(err) => {
+            warn!(
+                "Couldn't parse the recently opened list in {}! {err}",
+                path.display()
+            );
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn read_recently_opened_from_state_db(path: &Path) -> anyhow::Result<Option<sonic_rs::Value>> {
+    debug!(
+        "Ignoring {}, built without the `sqlite` feature",
+        path.display()
+    );
+    Ok(None)
+}
+
+/// Collects the uris of an `openedPathsList` / `history.recentlyOpenedPathsList` value.
+fn push_opened_paths_list_uris<'a>(
+    list: &'a sonic_rs::Value,
+    uris: &mut Vec<(RecentEntryType, &'a str)>,
+) {
+    let entries = list.get("entries").and_then(|v| v.as_array());
+    for entry in entries.into_iter().flat_map(|a| a.iter()) {
+        if let Some(uri) = entry.get("folderUri").and_then(|v| v.as_str()) {
+            uris.push((RecentEntryType::Dir, uri));
+        } else if let Some(uri) = entry.get("fileUri").and_then(|v| v.as_str()) {
+            uris.push((RecentEntryType::File, uri));
+        }
+    }
+    for (key, t) in [
+        ("workspaces3", RecentEntryType::Dir),
+        ("workspaces2", RecentEntryType::Dir),
+        ("files2", RecentEntryType::File),
+    ] {
+        let items = list.get(key).and_then(|v| v.as_array());
+        for item in items.into_iter().flat_map(|a| a.iter()) {
+            if let Some(uri) = item.as_str() {
+                uris.push((t.clone(), uri));
+            }
+        }
+    }
+}
+
+/// Keeps the local uris of the wanted types, dropping duplicates.
+fn recent_entries_from_uris(
+    uris: Vec<(RecentEntryType, &str)>,
+    with_files: bool,
+    with_dirs: bool,
+) -> Vec<RecentEntry<'_>> {
+    let mut seen = HashSet::new();
+    uris.into_iter()
+        .filter(|(t, _)| match t {
+            RecentEntryType::File => with_files,
+            RecentEntryType::Dir => with_dirs,
+        })
+        .filter_map(|(t, uri)| {
+            Some(RecentEntry {
+                t,
+                val: uri.strip_prefix("file://")?,
+            })
+        })
+        .filter(|entry| seen.insert(entry.val))
+        .collect()
+}
+
+/// Reads the folders of the workspaces in `workspaceStorage`, newest first.
+pub fn workspace_entries(
+    user_dir: &Path,
+    options: &WorkspacesOptions,
+    filter: &Filter,
+    sink: &mut impl Sink,
+) -> anyhow::Result<()> {
+    sink.emit_all(read_workspace_entries(user_dir, options, filter)?)
+}
+
+fn read_workspace_entries(
+    user_dir: &Path,
+    options: &WorkspacesOptions,
+    filter: &Filter,
+) -> anyhow::Result<Vec<Record>> {
+    let WorkspacesOptions {
+        with_dirs,
+        with_remotes,
+        create_display_strings,
+        max_age_days,
+        after,
+        before,
+        limit,
+        first_only,
+        sort,
+        reverse,
+        windows_paths,
+        control_chars,
+        keep_placeholders,
+    } = options;
+    let digest_options = DigestOptions {
+        with_local: *with_dirs,
+        local_kind: RecordKind::Dir,
+        with_remotes: *with_remotes,
+        create_display_strings: *create_display_strings,
+        windows_paths: *windows_paths,
+        control_chars: *control_chars,
+        keep_placeholders: *keep_placeholders,
+        source: RecordSource::Workspace,
+    };
+    let storage_path = get_workspace_storage_path(user_dir);
+
+    let min_system_time = max_age_days
+        .map(get_min_system_time_from_max_age_days)
+        .transpose()?
+        .max(*after);
+
+    let entries = fs::read_dir(&storage_path)?
+        .filter_map(|entry| {
+            get_workspace_storage_dir_entry(entry)
+                .inspect_err(|err| {
+                    debug!(
+                        "Error reading workspace entry in {}! {err}",
+                        storage_path.display()
+                    )
+                })
+                .ok()
+        })
+        .filter(|entry| {
+            if let Some(min_system_time) = min_system_time {
+                if entry.last_modified_at < min_system_time {
+                    return false;
+                }
+            }
+            !before.is_some_and(|before| entry.last_modified_at > before)
+        });
+
+    let limit = limit.unwrap_or(usize::MAX);
+    let read_all = *sort != SortKey::Mtime || *reverse;
+    let read_limit = if read_all { usize::MAX } else { limit };
+    let first_only = first_only.unwrap_or(usize::MAX);
+    let digest_limit = if read_all { usize::MAX } else { first_only };
+
+    let entries = newest_folder_entries(entries, read_limit);
+    let unreadable = AtomicUsize::new(0);
+    let mut records = digest_folder_entries(entries, digest_limit, |entry| {
+        let path = entry.path.join("workspace.json");
+        let target = match read_workspace_storage_target(&path) {
+            Ok(target) => target,
+            Err(err) => {
+                unreadable.fetch_add(1, atomic::Ordering::Relaxed);
+                debug!("Error reading {}! {err}", path.display());
+                return Vec::new();
+            }
+        };
+        let records = digest_workspace_storage_target(
+            target,
+            filter,
+            entry.last_modified_at,
+            &digest_options,
+        )
+        .inspect_err(|err| debug!("Error digesting {}! {err}", path.display()))
+        .unwrap_or_default();
+        trace!("{} entries from {}", records.len(), path.display());
+        records
+    });
+    // Every single one is logged with `--verbose` already.
+    let unreadable = unreadable.into_inner();
+    if unreadable > 0 && !log_enabled!(Level::Debug) {
+        let s = if unreadable == 1 { "" } else { "s" };
+        warn!("Skipped {unreadable} unreadable workspace file{s}!");
+    }
+    if read_all {
+        sort_records(&mut records, *sort, *reverse);
+        records.truncate(limit);
+    }
+    records.truncate(first_only);
+    Ok(records)
+}
+
+/// Merges the recent, workspace and history entries, newest first. Entries pointing to the
+/// same path are merged, keeping the newest one. Recent entries don't have a timestamp
+/// unless `max_age_days` is given and are placed last, in menubar order.
+pub fn all_entries(
+    user_dir: &Path,
+    options: &AllOptions,
+    filter: &Filter,
+    sink: &mut impl Sink,
+) -> anyhow::Result<()> {
+    sink.emit_all(read_all_entries(user_dir, options, filter)?)
+}
+
+fn read_all_entries(
+    user_dir: &Path,
+    options: &AllOptions,
+    filter: &Filter,
+) -> anyhow::Result<Vec<Record>> {
+    let AllOptions {
+        with_files,
+        with_dirs,
+        with_remotes,
+        create_display_strings,
+        max_age_days,
+        after,
+        before,
+        limit,
+        windows_paths,
+        control_chars,
+        keep_placeholders,
+    } = *options;
+
+    let recent_options = RecentOptions {
+        with_files,
+        with_dirs,
+        order: RecentOrder::Unchanged,
+        max_age_days,
+        limit: None,
+        windows_paths,
+        control_chars,
+    };
+    let workspaces_options = WorkspacesOptions {
+        with_dirs,
+        with_remotes,
+        create_display_strings,
+        max_age_days,
+        after,
+        before,
+        limit: None,
+        windows_paths,
+        control_chars,
+        keep_placeholders,
+        ..Default::default()
+    };
+    let history_options = HistoryOptions {
+        with_files,
+        with_remotes,
+        create_display_strings,
+        max_age_days,
+        after,
+        before,
+        windows_paths,
+        control_chars,
+        keep_placeholders,
+        ..Default::default()
+    };
+
+    let mut records = Vec::new();
+    if with_files || with_dirs {
+        let recent = read_recent_entries(user_dir, &recent_options, filter)?;
+        // Without a timestamp they can't be placed between the bounds.
+        let in_bounds = |record: &Record| match record.last_modified_at {
+            _ if after.is_none() && before.is_none() => true,
+            Some(t) => after.is_none_or(|after| t >= after) && before.is_none_or(|b| t <= b),
+            None => false,
+        };
+        records.extend(recent.into_iter().filter(in_bounds).map(|mut record| {
+            if create_display_strings {
+                record.display = Some(DisplayInfo {
+                    val: record.path.clone(),
+                    hint: None,
+                });
+            }
+            record
+        }));
+    }
+    records.extend(read_workspace_entries(
+        user_dir,
+        &workspaces_options,
+        filter,
+    )?);
+    records.extend(read_history_entries(user_dir, &history_options, filter)?);
+
+    records.sort_by(|r1, r2| cmp_newest_first(r1.last_modified_at, r2.last_modified_at));
+
+    let mut seen = HashSet::new();
+    records.retain(|record| {
+        let path = normalize_uri(&record.path);
+        let key = path.strip_prefix("file://").unwrap_or(&path).to_owned();
+        seen.insert(key)
+    });
+    records.truncate(limit.unwrap_or(usize::MAX));
+    Ok(records)
+}
+
+/// Totals over everything found in the editor's storage.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Stats {
+    pub workspace_storage_dirs: usize,
+    pub workspace_entries: usize,
+    pub history_entries: usize,
+    pub recent_entries: usize,
+    /// Local entries whose path exists.
+    pub existing: usize,
+    /// Local entries whose path doesn't exist.
+    pub missing: usize,
+    pub remote_types: BTreeMap<String, usize>,
+}
+
+impl Stats {
+    /// Adds the totals of `other`, like those of another config root.
+    pub fn add(&mut self, other: Stats) {
+        self.workspace_storage_dirs += other.workspace_storage_dirs;
+        self.workspace_entries += other.workspace_entries;
+        self.history_entries += other.history_entries;
+        self.recent_entries += other.recent_entries;
+        self.existing += other.existing;
+        self.missing += other.missing;
+        for (remote_type, count) in other.remote_types {
+            *self.remote_types.entry(remote_type).or_default() += count;
+        }
+    }
+}
+
+/// Counts the entries of every source. Sources that can't be read are reported and
+/// counted as empty.
+pub fn stats(user_dir: &Path, filter: &Filter) -> anyhow::Result<Stats> {
+    let mut stats = Stats::default();
+
+    let storage_path = get_workspace_storage_path(user_dir);
+    match fs::read_dir(&storage_path) {
+        Ok(entries) => {
+            stats.workspace_storage_dirs = entries
+                .filter_map(Result::ok)
+                .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+                .count();
+        }
+        Err(err) => warn!("Failed reading {}! {err}", storage_path.display()),
+    }
+
+    let warn_empty = |source: &str, err: anyhow::Error| {
+        warn!("Failed reading {source} entries! {err}");
+        Vec::new()
+    };
+    let recent = read_recent_entries(
+        user_dir,
+        &RecentOptions {
+            with_files: true,
+            with_dirs: true,
+            ..Default::default()
+        },
+        filter,
+    )
+    .unwrap_or_else(|err| warn_empty("recent", err));
+    let workspaces = read_workspace_entries(
+        user_dir,
+        &WorkspacesOptions {
+            with_dirs: true,
+            with_remotes: true,
+            ..Default::default()
+        },
+        filter,
+    )
+    .unwrap_or_else(|err| warn_empty("workspace", err));
+    let history = read_history_entries(
+        user_dir,
+        &HistoryOptions {
+            with_files: true,
+            with_remotes: true,
+            dedupe: false,
+            ..Default::default()
+        },
+        filter,
+    )
+    .unwrap_or_else(|err| warn_empty("history", err));
+
+    stats.recent_entries = recent.len();
+    stats.workspace_entries = workspaces.len();
+    stats.history_entries = history.len();
+
+    for record in recent.iter().chain(&workspaces).chain(&history) {
+        if let Some(remote_type) = &record.remote_type {
+            *stats.remote_types.entry(remote_type.clone()).or_default() += 1;
+            continue;
+        }
+        if record.kind == RecordKind::Remote {
+            continue;
+        }
+        let path = record.path.strip_prefix("file://").unwrap_or(&record.path);
+        if fs::exists(local_path_from_uri_path(path, false).as_ref()).unwrap_or(false) {
+            stats.existing += 1;
+        } else {
+            stats.missing += 1;
+        }
+    }
+    Ok(stats)
+}
+
+/// A directory in `workspaceStorage` that can be removed.
+#[derive(Clone, Debug)]
+pub struct OrphanedStorage {
+    pub path: PathBuf,
+    /// Size of the directory in bytes.
+    pub size: u64,
+}
+
+/// Finds the directories in `workspaceStorage` whose local folder or workspace file doesn't
+/// exist anymore, or that are older than `max_age_days`. Remote workspaces and ones on
+/// network shares are never returned.
+pub fn orphaned_workspace_storage(
+    user_dir: &Path,
+    max_age_days: Option<u32>,
+) -> anyhow::Result<Vec<OrphanedStorage>> {
+    let storage_path = get_workspace_storage_path(user_dir);
+
+    let min_system_time = max_age_days
+        .map(get_min_system_time_from_max_age_days)
+        .transpose()?;
+
+    let mut orphans = Vec::new();
+    for entry in fs::read_dir(&storage_path)? {
+        let FolderEntry {
+            path,
+            last_modified_at,
+        } = match get_workspace_storage_dir_entry(entry) {
+            Ok(entry) => entry,
+            Err(err) => {
+                debug!(
+                    "Error reading workspace entry in {}! {err}",
+                    storage_path.display()
+                );
+                continue;
+            }
+        };
+        let workspace_json_path = path.join("workspace.json");
+        let target = match read_workspace_storage_target(&workspace_json_path) {
+            Ok(target) => target,
+            Err(err) => {
+                debug!("Error digesting {}! {err}", workspace_json_path.display());
+                continue;
+            }
+        };
+        let is_missing = match &target {
+            Some(target) => {
+                let Ok(uri) = urlencoding::decode(target.uri()) else {
+                    continue;
+                };
+                let Some(uri_path) = uri.strip_prefix("file://") else {
+                    continue;
+                };
+                // Network shares may just be unmounted.
+                if file_uri_host(uri_path).is_some() {
+                    continue;
+                }
+                let local_path = local_path_from_uri_path(uri_path, false);
+                !fs::exists(local_path.as_ref()).unwrap_or(true)
+            }
+            None => false,
+        };
+        let is_expired = min_system_time.is_some_and(|t| last_modified_at < t);
+        if is_missing || is_expired {
+            let size = get_dir_size(&path);
+            orphans.push(OrphanedStorage { path, size });
+        }
+    }
+    orphans.sort_by(|o1, o2| o1.path.cmp(&o2.path));
+    Ok(orphans)
+}
+
+fn get_dir_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| match entry.file_type() {
+            Ok(t) if t.is_dir() => get_dir_size(&entry.path()),
+            Ok(_) => entry.metadata().map(|m| m.len()).unwrap_or(0),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// Reads the resources of the local history in `User/History`, newest first.
+pub fn history_entries(
+    user_dir: &Path,
+    options: &HistoryOptions,
+    filter: &Filter,
+    sink: &mut impl Sink,
+) -> anyhow::Result<()> {
+    sink.emit_all(read_history_entries(user_dir, options, filter)?)
+}
+
+fn read_history_entries(
+    user_dir: &Path,
+    options: &HistoryOptions,
+    filter: &Filter,
+) -> anyhow::Result<Vec<Record>> {
+    let HistoryOptions {
+        with_files,
+        with_remotes,
+        create_display_strings,
+        max_age_days,
+        after,
+        before,
+        limit,
+        first_only,
+        order,
+        frecency_halflife_days,
+        dedupe,
+        sort,
+        reverse,
+        windows_paths,
+        control_chars,
+        keep_placeholders,
+    } = options;
+    let digest_options = DigestOptions {
+        with_local: *with_files,
+        local_kind: RecordKind::File,
+        with_remotes: *with_remotes,
+        create_display_strings: *create_display_strings,
+        windows_paths: *windows_paths,
+        control_chars: *control_chars,
+        keep_placeholders: *keep_placeholders,
+        source: RecordSource::History,
+    };
+    let storage_path = get_history_path(user_dir);
+
+    let min_system_time = max_age_days
+        .map(get_min_system_time_from_max_age_days)
+        .transpose()?
+        .max(*after);
+
+    let entries =
+        fs::read_dir(&storage_path)?.filter_map(|entry| match get_data_from_dir_entry(entry) {
+            Err(err) => {
+                debug!(
+                    "Error reading history entry in {}! {err}",
+                    storage_path.display()
+                );
+                None
+            }
+            Ok(entry) => {
+                if let Some(min_system_time) = min_system_time {
+                    if entry.last_modified_at < min_system_time {
+                        return None;
+                    }
+                }
+                if before.is_some_and(|before| entry.last_modified_at > before) {
+                    return None;
+                }
+                Some(entry)
+            }
+        });
+
+    let limit = limit.unwrap_or(usize::MAX);
+    let read_all = *order != HistoryOrder::Mtime || *sort != SortKey::Mtime || *reverse;
+    let read_limit = if read_all { usize::MAX } else { limit };
+    let first_only = first_only.unwrap_or(usize::MAX);
+    // Duplicates only turn out after digesting.
+    let digest_limit = if read_all || *dedupe {
+        usize::MAX
+    } else {
+        first_only
+    };
+
+    let entries = newest_folder_entries(entries, read_limit);
+    let mut records = digest_folder_entries(entries, digest_limit, |entry| {
+        let path = entry.path.join("entries.json");
+        let record =
+            digest_history_dir_entry(&path, filter, entry.last_modified_at, &digest_options)
+                .inspect_err(|err| debug!("Error digesting {}! {err}", path.display()))
+                .ok()
+                .flatten();
+        trace!(
+            "{} entries from {}",
+            usize::from(record.is_some()),
+            path.display()
+        );
+        record
+    });
+    if *order == HistoryOrder::Frecency {
+        records = order_by_frecency(records, *frecency_halflife_days);
+    } else if *dedupe {
+        // Still newest first, so the newest entry of a resource is kept.
+        let mut seen = HashSet::new();
+        records.retain(|record| seen.insert(normalize_uri(&record.path).into_owned()));
+    }
+    if *sort != SortKey::Mtime || *reverse {
+        sort_records(&mut records, *sort, *reverse);
+    }
+    records.truncate(limit.min(first_only));
+    Ok(records)
+}
+
+/// The `n` newest of `entries`, newest first. With a bound only `n` entries are kept in a
+/// min-heap instead of sorting all of them.
+fn newest_folder_entries(entries: impl Iterator<Item = FolderEntry>, n: usize) -> Vec<FolderEntry> {
+    if n == usize::MAX {
+        let mut entries = entries.collect::<Vec<_>>();
+        entries.sort_by(|e1, e2| e1.last_modified_at.cmp(&e2.last_modified_at).reverse());
+        return entries;
+    }
+    let mut heap = BinaryHeap::with_capacity(n.saturating_add(1).min(1024));
+    for entry in entries {
+        heap.push(Reverse((entry.last_modified_at, entry.path)));
+        if heap.len() > n {
+            heap.pop();
+        }
+    }
+    // Ascending by `Reverse` is newest first.
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|Reverse((last_modified_at, path))| FolderEntry {
+            path,
+            last_modified_at,
+        })
+        .collect()
+}
+
+/// Digests `entries` in order until `digest_limit` records were found. Without a limit, the
+/// `parallel` feature digests them on all cores.
+fn digest_folder_entries<R>(
+    entries: Vec<FolderEntry>,
+    digest_limit: usize,
+    digest: impl Fn(FolderEntry) -> R + Sync + Send,
+) -> Vec<Record>
+where
+    R: IntoIterator<Item = Record>,
+{
+    #[cfg(feature = "parallel")]
+    if digest_limit == usize::MAX {
+        use rayon::iter::{IntoParallelIterator, ParallelIterator};
+        return entries.into_par_iter().flat_map_iter(digest).collect();
+    }
+    let mut records = Vec::new();
+    for entry in entries {
+        if records.len() >= digest_limit {
+            break;
+        }
+        records.extend(digest(entry));
+    }
+    records
+}
+
+/// Sorting by [`SortKey::Mtime`] keeps the order of `records`, which is expected to be
+/// newest first (or ranked).
+fn sort_records(records: &mut [Record], sort: SortKey, reverse: bool) {
+    match sort {
+        SortKey::Mtime => {}
+        SortKey::Name => records.sort_by(|r1, r2| record_name(r1).cmp(record_name(r2))),
+        SortKey::Path => records.sort_by(|r1, r2| record_path(r1).cmp(record_path(r2))),
+    }
+    if reverse {
+        records.reverse();
+    }
+}
+
+fn record_path(record: &Record) -> &str {
+    match &record.display {
+        Some(display) => &display.val,
+        None => record.path.strip_prefix("file://").unwrap_or(&record.path),
+    }
+}
+
+fn record_name(record: &Record) -> &str {
+    let path = record_path(record);
+    let path = path.strip_suffix('/').unwrap_or(path);
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// Expects `records` to be sorted newest first.
+fn order_by_frecency(records: Vec<Record>, halflife_days: f64) -> Vec<Record> {
+    const NUM_SECONDS_IN_DAY: f64 = 86400.0;
+    let now = SystemTime::now();
+    let mut ranked: Vec<(Record, usize)> = Vec::new();
+    let mut indices: HashMap<String, usize> = HashMap::new();
+    for record in records {
+        match indices.get(&record.path) {
+            Some(&i) => ranked[i].1 += 1,
+            None => {
+                indices.insert(record.path.clone(), ranked.len());
+                ranked.push((record, 1));
+            }
+        }
+    }
+    let score = |(record, frequency): &(Record, usize)| {
+        let age_days = record
+            .last_modified_at
+            .and_then(|t| now.duration_since(t).ok())
+            .map(|d| d.as_secs_f64() / NUM_SECONDS_IN_DAY)
+            .unwrap_or_default();
+        *frequency as f64 * 0.5f64.powf(age_days / halflife_days)
+    };
+    ranked.sort_by(|r1, r2| score(r2).total_cmp(&score(r1)));
+    ranked.into_iter().map(|(record, _)| record).collect()
+}
+
+/// Parses `YYYY-MM-DD` (local midnight) or an RFC 3339 timestamp.
+pub fn parse_date(s: &str) -> anyhow::Result<SystemTime> {
+    if let Ok(date_time) = chrono::DateTime::parse_from_rfc3339(s) {
+        return Ok(date_time.into());
+    }
+    let date = chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map_err(|_| anyhow!("Invalid date `{s}`, expected `YYYY-MM-DD` or RFC 3339!"))?;
+    let date_time = date
+        .and_time(chrono::NaiveTime::MIN)
+        .and_local_timezone(chrono::Local)
+        .earliest()
+        .ok_or_else(|| anyhow!("Invalid local date `{s}`!"))?;
+    Ok(date_time.into())
+}
+
+fn get_min_system_time_from_max_age_days(max_age_days: u32) -> anyhow::Result<SystemTime> {
+    const NUM_SECONDS_IN_DAY: u64 = 86400;
+    let res = SystemTime::now()
+        .checked_sub(Duration::from_secs(
+            (max_age_days as u64) * NUM_SECONDS_IN_DAY,
+        ))
+        .ok_or_else(|| anyhow!("`max-age-days` too big"))?;
+    Ok(res)
+}
+
+/// Orders newer times first and missing times last.
+fn cmp_newest_first(t1: Option<SystemTime>, t2: Option<SystemTime>) -> Ordering {
+    match (t1, t2) {
+        (Some(t1), Some(t2)) => t1.cmp(&t2).reverse(),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+fn get_last_modified_at_from_path(path: impl AsRef<Path>) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[derive(Clone, Debug)]
+struct FolderEntry {
+    path: PathBuf,
+    last_modified_at: SystemTime,
+}
+
+fn digest_history_dir_entry(
+    path: &Path,
+    filter: &Filter,
+    last_modified_at: SystemTime,
+    options: &DigestOptions,
+) -> anyhow::Result<Option<Record>> {
+    if !fs::exists(path)? {
+        return Ok(None);
+    }
+    let v = read_entry_file(path)?;
+    let value: sonic_rs::Value = sonic_rs::from_slice(&v)
+        .map_err(|err| anyhow!("Failed parsing {}! {err}", path.display()))?;
+
+    let Ok(field) = value.as_object_get_result("resource") else {
+        return Ok(None);
+    };
+    let val = field.as_str_result()?;
+    digest_folder_uri(val, filter, last_modified_at, options)
+}
+
+fn digest_workspace_storage_target(
+    target: Option<WorkspaceStorageTarget>,
+    filter: &Filter,
+    last_modified_at: SystemTime,
+    options: &DigestOptions,
+) -> anyhow::Result<Vec<Record>> {
+    match target {
+        Some(WorkspaceStorageTarget::Workspace(val)) => {
+            digest_code_workspace_file(&val, filter, last_modified_at, options)
+        }
+        Some(WorkspaceStorageTarget::Folder(val)) => {
+            let record = digest_folder_uri(&val, filter, last_modified_at, options)?;
+            Ok(record.into_iter().collect())
+        }
+        None => Ok(Vec::new()),
+    }
+}
+
+enum WorkspaceStorageTarget {
+    /// Uri of a `.code-workspace` file.
+    Workspace(String),
+    /// Uri of a single folder.
+    Folder(String),
+}
+
+impl WorkspaceStorageTarget {
+    fn uri(&self) -> &str {
+        match self {
+            WorkspaceStorageTarget::Workspace(uri) | WorkspaceStorageTarget::Folder(uri) => uri,
+        }
+    }
+}
+
+/// Newest mtime of `paths`, their children and grandchildren, like the files in the
+/// directories of `workspaceStorage` and `History`.
+pub fn newest_mtime(paths: &[PathBuf]) -> Option<SystemTime> {
+    fn newest(path: &Path, depth: u8) -> Option<SystemTime> {
+        let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+        let children = match fs::read_dir(path) {
+            Ok(entries) if depth > 0 => entries
+                .filter_map(Result::ok)
+                .filter_map(|entry| newest(&entry.path(), depth - 1))
+                .max(),
+            _ => None,
+        };
+        modified.max(children)
+    }
+    paths.iter().filter_map(|path| newest(path, 2)).max()
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecordCache {
+    key: String,
+    newest: Option<SystemTime>,
+    /// The `.code-workspace` files the records were read from, which live outside of `sources`.
+    workspace_files: Vec<(PathBuf, Option<SystemTime>)>,
+    records: Vec<Record>,
+}
+
+fn workspace_file_mtimes(records: &[Record]) -> Vec<(PathBuf, Option<SystemTime>)> {
+    let files: BTreeSet<&PathBuf> = records
+        .iter()
+        .filter_map(|r| r.workspace.as_ref())
+        .collect();
+    files
+        .into_iter()
+        .map(|path| {
+            let mtime = fs::metadata(path).and_then(|m| m.modified()).ok();
+            (path.clone(), mtime)
+        })
+        .collect()
+}
+
+/// The entries `collect` finds, from the cache file `name` in `cache_dir` as long as it was
+/// written for the same `key` and neither `sources` nor the `.code-workspace` files of the
+/// entries changed since. A missing source counts as unchanged while it stays missing.
+pub fn cached_records(
+    cache_dir: &Path,
+    name: &str,
+    key: &str,
+    sources: &[PathBuf],
+    collect: impl FnOnce(&mut Vec<Record>) -> anyhow::Result<()>,
+) -> anyhow::Result<Vec<Record>> {
+    let cache_path = cache_dir.join(format!("{name}.json"));
+    // Taken before reading, so changes made while reading invalidate the cache.
+    let newest = newest_mtime(sources);
+    let cache = fs::read(&cache_path)
+        .ok()
+        .and_then(|content| sonic_rs::from_slice::<RecordCache>(&content).ok());
+    if let Some(cache) = cache {
+        let unchanged = |(path, mtime): &(PathBuf, Option<SystemTime>)| {
+            fs::metadata(path).and_then(|m| m.modified()).ok() == *mtime
+        };
+        if cache.key == key && cache.newest == newest && cache.workspace_files.iter().all(unchanged)
+        {
+            trace!("Using {}", cache_path.display());
+            return Ok(cache.records);
+        }
+    }
+
+    let mut records = Vec::new();
+    collect(&mut records)?;
+    let cache = RecordCache {
+        key: key.to_owned(),
+        newest,
+        workspace_files: workspace_file_mtimes(&records),
+        records,
+    };
+    let written = fs::create_dir_all(cache_dir)
+        .map_err(anyhow::Error::from)
+        .and_then(|()| Ok(fs::write(&cache_path, sonic_rs::to_vec(&cache)?)?));
+    if let Err(err) = written {
+        warn!("Failed writing cache {}! {err}", cache_path.display());
+    }
+    Ok(cache.records)
+}
+
+fn read_workspace_storage_target(path: &Path) -> anyhow::Result<Option<WorkspaceStorageTarget>> {
+    if !fs::exists(path)? {
+        return Ok(None);
+    }
+    let v = read_entry_file(path)?;
+    let value: sonic_rs::Value = sonic_rs::from_slice(&v)
+        .map_err(|err| anyhow!("Failed parsing {}! {err}", path.display()))?;
+
+    if let Ok(field) = value.as_object_get_result("workspace") {
+        let val = field.as_str_result()?;
+        return Ok(Some(WorkspaceStorageTarget::Workspace(val.to_owned())));
+    }
+
+    let Ok(field) = value.as_object_get_result("folder") else {
+        return Ok(None);
+    };
+    let val = field.as_str_result()?;
+    Ok(Some(WorkspaceStorageTarget::Folder(val.to_owned())))
+}
+
+fn digest_code_workspace_file(
+    val: &str,
+    filter: &Filter,
+    last_modified_at: SystemTime,
+    options: &DigestOptions,
+) -> anyhow::Result<Vec<Record>> {
+    let val = urlencoding::decode(val)?;
+    let Some(workspace_path) = val.strip_prefix("file://") else {
+        return Ok(Vec::new());
+    };
+    let workspace_path = local_path_from_uri_path(workspace_path, options.windows_paths);
+    let workspace_path = Path::new(workspace_path.as_ref());
+    let workspace_dir = workspace_path.parent().unwrap_or(Path::new("/"));
+    let workspace_name = workspace_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let v = read_entry_file(workspace_path)?;
+    let value: sonic_rs::Value = sonic_rs::from_slice(&v)
+        .map_err(|err| anyhow!("Failed parsing {}! {err}", workspace_path.display()))?;
+    let folders = value
+        .as_object_get_result("folders")?
+        .as_array()
+        .ok_or_else(|| anyhow!("Failed using field in json as an array!"))?;
+
+    let mut records = Vec::new();
+    // The same folder may be listed more than once, e.g. by a relative and an absolute path.
+    let mut seen = HashSet::new();
+    for folder in folders.iter() {
+        let record = if let Ok(uri) = folder.as_object_get_result("uri") {
+            digest_folder_uri(uri.as_str_result()?, filter, last_modified_at, options)?
+        } else {
+            let path = Path::new(folder.as_object_get_result("path")?.as_str_result()?);
+            let path = normalize_path(&workspace_dir.join(path));
+            digest_decoded_folder_uri(
+                &file_uri_from_path(&path.to_string_lossy()),
+                filter,
+                last_modified_at,
+                options,
+            )?
+        };
+        let Some(mut record) = record else {
+            continue;
+        };
+        if !seen.insert(normalize_uri(&record.path).into_owned()) {
+            continue;
+        }
+        if let Some(display) = &mut record.display {
+            display.hint.get_or_insert_with(|| DisplayInfoHint {
+                remote_type: "Workspace".to_owned(),
+                addition: Some(workspace_name.clone()),
+            });
+        }
+        record.workspace = Some(workspace_path.to_owned());
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Trims a single trailing slash and, on Windows, lowercases the drive letter of `file://`
+/// uris, so that variants of the same location compare equal.
+pub fn normalize_uri(uri: &str) -> Cow<'_, str> {
+    let mut uri = Cow::Borrowed(uri);
+    if let Some(trimmed) = uri.strip_suffix('/') {
+        if !trimmed.is_empty() && !trimmed.ends_with('/') {
+            uri = Cow::Owned(trimmed.to_owned());
+        }
+    }
+    if cfg!(windows) {
+        if let Some(path) = uri.strip_prefix("file:///") {
+            let bytes = path.as_bytes();
+            if bytes.len() >= 2 && bytes[0].is_ascii_uppercase() && bytes[1] == b':' {
+                let mut owned = uri.into_owned();
+                owned[8..9].make_ascii_lowercase();
+                uri = Cow::Owned(owned);
+            }
+        }
+    }
+    uri
+}
+
+/// Resolves `.` and `..` components without touching the file system.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut res = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                res.pop();
+            }
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+/// What to do with the characters that would break the tab separated, newline or NUL
+/// terminated output in paths.
+#[derive(Debug, Clone, Copy, Default, ValueEnum, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ControlChars {
+    /// Remove them.
+    #[default]
+    Strip,
+    /// Replace them with `\t`, `\n`, `\r` and `\0`.
+    Escape,
+    /// Keep them as they are.
+    Keep,
+}
+
+impl ControlChars {
+    pub fn apply(self, s: &str) -> Cow<'_, str> {
+        match self {
+            ControlChars::Strip => strip_control_chars(s),
+            ControlChars::Escape => escape_control_chars(s),
+            ControlChars::Keep => Cow::Borrowed(s),
+        }
+    }
+}
+
+/// Backslash-escapes `\t`, `\n`, `\r` and `\0`.
+pub fn escape_control_chars(s: &str) -> Cow<'_, str> {
+    if !s.contains(CONTROL_CHARS) {
+        return Cow::Borrowed(s);
+    }
+    let mut res = String::with_capacity(s.len() + 2);
+    for c in s.chars() {
+        match c {
+            '\t' => res.push_str("\\t"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            '\0' => res.push_str("\\0"),
+            c => res.push(c),
+        }
+    }
+    Cow::Owned(res)
+}
+
+const CONTROL_CHARS: [char; 4] = ['\t', '\n', '\r', '\0'];
+
+/// Removes the characters that would break the tab separated, newline or NUL terminated
+/// output (`\t`, `\n`, `\r` and `\0`).
+pub fn strip_control_chars(s: &str) -> Cow<'_, str> {
+    if !s.contains(CONTROL_CHARS) {
+        return Cow::Borrowed(s);
+    }
+    Cow::Owned(s.replace(CONTROL_CHARS, ""))
+}
+
+#[derive(Clone, Copy, Debug)]
+struct DigestOptions {
+    /// Keep `file://` uris, as entries of `local_kind`.
+    with_local: bool,
+    local_kind: RecordKind,
+    with_remotes: bool,
+    create_display_strings: bool,
+    windows_paths: bool,
+    control_chars: ControlChars,
+    keep_placeholders: bool,
+    source: RecordSource,
+}
+
+/// Paths of entries that would show up as (nearly) blank rows, like the ones of throwaway
+/// untitled workspaces.
+fn is_placeholder_path(path: &str) -> bool {
+    // Local paths start with a slash, the ones of remotes don't.
+    let path = path.trim().trim_start_matches(['/', '\\']);
+    path.is_empty()
+        || path.eq_ignore_ascii_case("untitled")
+        || path.starts_with("Untitled (Workspace)")
+}
+
+/// Host of the part of a `file://` uri after the scheme, if it isn't empty or `localhost`.
+fn file_uri_host(path: &str) -> Option<&str> {
+    if path.is_empty() || path.starts_with('/') {
+        return None;
+    }
+    let host = path.split_once('/').map_or(path, |(host, _)| host);
+    (!host.eq_ignore_ascii_case("localhost")).then_some(host)
+}
+
+/// The (decoded) `file://` uri of a local path, like `c:\Users` or `\\host\share`.
+fn file_uri_from_path(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    match path.strip_prefix("//") {
+        Some(unc) => format!("file://{unc}"),
+        None if path.starts_with('/') => format!("file://{path}"),
+        None => format!("file:///{path}"),
+    }
+}
+
+/// Turns the part of a `file://` uri after the scheme into a local path. Uris with a host
+/// (`file://host/share/path`) become UNC paths (`//host/share/path`). Strips the slash in
+/// front of Windows drive letters (`/c:/Users` -> `c:/Users`) and, with `windows_paths` or
+/// on Windows, uses backslashes as separators.
+pub fn local_path_from_uri_path(path: &str, windows_paths: bool) -> Cow<'_, str> {
+    let windows_paths = windows_paths || cfg!(windows);
+    let mut path = path;
+    if file_uri_host(path).is_some() {
+        let unc = format!("//{path}");
+        return Cow::Owned(if windows_paths {
+            unc.replace('/', "\\")
+        } else {
+            unc
+        });
+    }
+    if !path.is_empty() && !path.starts_with('/') {
+        // `localhost`
+        path = path.find('/').map_or("", |i| &path[i..]);
+    }
+    let bytes = path.as_bytes();
+    let has_drive_letter =
+        bytes.len() >= 3 && bytes[0] == b'/' && bytes[1].is_ascii_alphabetic() && bytes[2] == b':';
+    let path = if has_drive_letter { &path[1..] } else { path };
+    if windows_paths && path.contains('/') {
+        Cow::Owned(path.replace('/', "\\"))
+    } else {
+        Cow::Borrowed(path)
+    }
+}
+
+fn digest_folder_uri(
+    val: &str,
+    filter: &Filter,
+    last_modified_at: SystemTime,
+    options: &DigestOptions,
+) -> anyhow::Result<Option<Record>> {
+    let val = urlencoding::decode(val)?;
+    digest_decoded_folder_uri(&val, filter, last_modified_at, options)
+}
+
+fn digest_decoded_folder_uri(
+    val: &str,
+    filter: &Filter,
+    last_modified_at: SystemTime,
+    options: &DigestOptions,
+) -> anyhow::Result<Option<Record>> {
+    let DigestOptions {
+        with_local,
+        local_kind,
+        with_remotes,
+        create_display_strings,
+        windows_paths,
+        ..
+    } = *options;
+    let starts_with_file = with_local && val.starts_with("file://");
+    let starts_with_remote = with_remotes && val.starts_with("vscode-remote://");
+
+    if !starts_with_file && !starts_with_remote {
+        return Ok(None);
+    }
+
+    let clean_val = options.control_chars.apply(val).into_owned();
+
+    let local_path = starts_with_file.then(|| local_path_from_uri_path(&val[7..], windows_paths));
+    if !options.keep_placeholders {
+        let path = match &local_path {
+            Some(local_path) => local_path.as_ref(),
+            None => val[16..].split_once('/').map_or("", |(_, path)| path),
+        };
+        if is_placeholder_path(path) {
+            debug!("Skipping placeholder entry `{clean_val}`");
+            return Ok(None);
+        }
+    }
+    if let Some(local_path) = &local_path {
+        if !filter.keep_local_path(local_path, &local_path_from_uri_path(&val[7..], false)) {
+            return Ok(None);
+        }
+    }
+
+    let (kind, remote_type) = if starts_with_file {
+        (local_kind, None)
+    } else {
+        let remote_type = val[16..].split_once('+').map(|(t, _)| t.to_owned());
+        (RecordKind::Remote, remote_type)
+    };
+
+    let remote_display = if starts_with_remote && (create_display_strings || filter.has_patterns())
+    {
+        match extract_folder_name_from_remote_val(&val[16..]) {
+            Err(err) => {
+                debug!("Couldn't parse `vscode-remote` folder-string! {err}");
+                Some(DisplayInfo {
+                    val: clean_val.clone(),
+                    hint: None,
+                })
+            }
+            Ok(r) => Some(r),
+        }
+    } else {
+        None
+    };
+
+    if let Some(remote_display) = &remote_display {
+        if !filter.matches_patterns(&remote_display.val) {
+            return Ok(None);
+        }
+    }
+
+    let display = if !create_display_strings {
+        None
+    } else if let Some(local_path) = local_path {
+        Some(DisplayInfo {
+            val: local_path.into_owned(),
+            // Tells network shares apart from local paths.
+            hint: file_uri_host(&val[7..]).map(|host| DisplayInfoHint {
+                remote_type: "Network".to_owned(),
+                addition: Some(host.to_owned()),
+            }),
+        })
+    } else {
+        remote_display
+    };
+
+    Ok(Some(Record {
+        path: clean_val,
+        kind,
+        remote_type,
+        display,
+        last_modified_at: Some(last_modified_at),
+        source: options.source,
+        workspace: None,
+    }))
+}
+
+fn extract_folder_name_from_remote_val(rest: &str) -> anyhow::Result<DisplayInfo> {
+    let remote_type_end = rest.find('+').ok_or_else(|| anyhow!("No space found!"))?;
+    let hex_start = remote_type_end + 1;
+    let hex_end = rest[hex_start..]
+        .find('/')
+        .ok_or_else(|| anyhow!("No slash found after first space!"))?
+        + hex_start;
+
+    let remote_type = &rest[..remote_type_end];
+
+    // The authority of WSL uris is the name of the distro, plain or as hex.
+    if remote_type == "wsl" {
+        let authority = &rest[hex_start..hex_end];
+        return Ok(DisplayInfo {
+            val: rest[hex_end..].to_owned(),
+            hint: Some(DisplayInfoHint {
+                remote_type: get_display_string_from_remote_type(remote_type).to_owned(),
+                addition: Some(decode_hex_authority(authority).unwrap_or(authority.to_owned())),
+            }),
+        });
+    }
+
+    if remote_type == "ssh-remote" {
+        return Ok(DisplayInfo {
+            val: rest[hex_end..].to_owned(),
+            hint: Some(DisplayInfoHint {
+                remote_type: get_display_string_from_remote_type(remote_type).to_owned(),
+                addition: Some(ssh_host_from_authority(&rest[hex_start..hex_end])),
+            }),
+        });
+    }
+
+    // Codespaces and tunnels use the plain name of the codespace or machine, which can
+    // look like hex.
+    if matches!(remote_type, "codespaces" | "tunnel") {
+        return Ok(DisplayInfo {
+            val: rest[hex_end..].to_owned(),
+            hint: Some(DisplayInfoHint {
+                remote_type: get_display_string_from_remote_type(remote_type).to_owned(),
+                addition: Some(rest[hex_start..hex_end].to_owned()),
+            }),
+        });
+    }
+    let remote_type = get_display_string_from_remote_type(remote_type);
+
+    // Hex decode
+    let Some(bytes) = (hex_start..hex_end)
+        .step_by(2)
+        .map(|i| u8::from_str_radix(rest.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()
+    else {
+        return Ok(DisplayInfo {
+            val: rest[hex_start..].to_owned(),
+            hint: Some(DisplayInfoHint {
+                remote_type: remote_type.to_string(),
+                addition: None,
+            }),
+        });
+    };
+
+    let v = String::from_utf8(bytes)
+        .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned());
+
+    let info = if let Some((val, addition)) = hint_addition_from_json_slice(&v) {
+        DisplayInfo {
+            val,
+            hint: Some(DisplayInfoHint {
+                remote_type: remote_type.to_string(),
+                addition: addition.map(str::to_owned),
+            }),
+        }
+    } else {
+        DisplayInfo {
+            val: v,
+            hint: Some(DisplayInfoHint {
+                remote_type: remote_type.to_string(),
+                addition: None,
+            }),
+        }
+    };
+
+    Ok(info)
+}
+
+/// Host of an `ssh-remote` authority. That is either the plain `[user@]host` or its hex,
+/// or the hex of a json object with a `hostName`.
+fn ssh_host_from_authority(authority: &str) -> String {
+    let host = match decode_hex_authority(authority) {
+        Some(decoded) => sonic_rs::from_str::<sonic_rs::Value>(&decoded)
+            .ok()
+            .and_then(|val| val.get("hostName")?.as_str().map(str::to_owned))
+            .unwrap_or(decoded),
+        None => authority.to_owned(),
+    };
+    match host.rsplit_once('@') {
+        Some((_, host)) => host.to_owned(),
+        None => host,
+    }
+}
+
+/// Decodes an authority given as hex. `None` if it isn't hex of printable text, as plain
+/// names can consist of hex digits too, but rarely decode to text.
+fn decode_hex_authority(authority: &str) -> Option<String> {
+    (0..authority.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(authority.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .filter(|s| !s.is_empty() && !s.contains(char::is_control))
+}
+
+/// Freedesktop icon name for an entry, e.g. for rofi or wofi.
+pub fn get_icon_name(kind: RecordKind, remote_type: Option<&str>) -> &'static str {
+    match (kind, remote_type) {
+        (RecordKind::File, _) => "text-x-generic",
+        (RecordKind::Dir, _) => "folder",
+        (RecordKind::Remote, Some("codespaces")) => "folder-github",
+        (RecordKind::Remote, _) => "folder-remote",
+    }
+}
+
+/// Nerd Font glyph of an entry, shown with `--icons`. The codepoints depend on the font,
+/// so they can be overridden in the config file.
+pub fn get_nerd_glyph(kind: RecordKind, remote_type: Option<&str>) -> &'static str {
+    match (kind, remote_type) {
+        (RecordKind::File, _) => "\u{f15b}",
+        (RecordKind::Dir, _) => "\u{f07b}",
+        (RecordKind::Remote, Some("ssh-remote")) => "\u{f120}",
+        (RecordKind::Remote, Some("dev-container")) => "\u{f308}",
+        (RecordKind::Remote, Some("wsl")) => "\u{f17c}",
+        (RecordKind::Remote, Some("codespaces")) => "\u{f09b}",
+        (RecordKind::Remote, Some("tunnel")) => "\u{f0ec}",
+        (RecordKind::Remote, _) => "\u{f0c2}",
+    }
+}
+
+/// Label of a remote type in display strings. Unknown types are kept as they are.
+pub fn get_display_string_from_remote_type(remote_type: &str) -> &str {
+    match remote_type {
+        "wsl" => "WSL",
+        "dev-container" => "Dev Container",
+        "ssh-remote" => "SSH Remote",
+        "codespaces" => "Codespaces",
+        "tunnel" => "Tunnel",
+        v => v,
+    }
+}
+
+fn hint_addition_from_json_slice(v: &str) -> Option<(String, Option<&'static str>)> {
+    let val: sonic_rs::Value = sonic_rs::from_str(v).ok()?;
+    let obj = val.as_object()?;
+    for path in ["hostPath", "repositoryPath", "volumeName", "codespaceName"] {
+        let Some(s) = obj.get(&path) else {
+            continue;
+        };
+        let Some(s) = s.as_str() else {
+            continue;
+        };
+        return Some((s.to_owned(), hint_addition_from_path(path)));
+    }
+    None
+}
+
+fn hint_addition_from_path(path: &str) -> Option<&'static str> {
+    match path {
+        "hostPath" => None,
+        "repositoryPath" => Some("repository"),
+        "volumeName" => Some("volume"),
+        "codespaceName" => Some("codespace"),
+        _ => Some("unknown"),
+    }
+}
+
+/// Upper bound for the size of a single `entries.json`, `workspace.json` or
+/// `.code-workspace` file.
+const MAX_ENTRY_FILE_SIZE: u64 = 1024 * 1024;
+
+fn read_entry_file(path: &Path) -> anyhow::Result<Vec<u8>> {
+    trace!("Reading {}", path.display());
+    let read = || -> std::io::Result<Vec<u8>> {
+        let mut v = Vec::new();
+        File::open(path)?
+            .take(MAX_ENTRY_FILE_SIZE + 1)
+            .read_to_end(&mut v)?;
+        Ok(v)
+    };
+    let v = read().map_err(|err| anyhow!("Failed reading {}! {err}", path.display()))?;
+    if v.len() as u64 > MAX_ENTRY_FILE_SIZE {
+        return Err(anyhow!(
+            "{} is bigger than {MAX_ENTRY_FILE_SIZE} bytes!",
+            path.display()
+        ));
+    }
+    Ok(v)
+}
+
+fn get_data_from_dir_entry(entry: Result<DirEntry, std::io::Error>) -> anyhow::Result<FolderEntry> {
+    let entry = entry?;
+    let path = entry.path();
+    // Relocated storage is often linked back into place.
+    let metadata = if entry.file_type()?.is_symlink() {
+        fs::metadata(&path)?
+    } else {
+        entry.metadata()?
+    };
+    if !metadata.is_dir() {
+        return Err(anyhow!("Didn't expect file type!"));
+    }
+    let last_modified_at = metadata.modified()?;
+    Ok(FolderEntry {
+        path,
+        last_modified_at,
+    })
+}
+
+/// Like [`get_data_from_dir_entry`], but dated by the workspace's `state.vscdb`. The editor
+/// only writes it while the workspace is open, while the directory's own mtime also changes
+/// when extensions create or remove their storage in it.
+fn get_workspace_storage_dir_entry(
+    entry: Result<DirEntry, std::io::Error>,
+) -> anyhow::Result<FolderEntry> {
+    let mut entry = get_data_from_dir_entry(entry)?;
+    if let Ok(last_opened_at) =
+        fs::metadata(entry.path.join("state.vscdb")).and_then(|metadata| metadata.modified())
+    {
+        entry.last_modified_at = last_opened_at;
+    }
+    Ok(entry)
+}
+
+trait SonicRsValueExtensions {
+    type ObjectType;
+    fn as_object_get_result<'a>(&'a self, key: &str) -> anyhow::Result<&'a sonic_rs::Value>;
+    /// Descends into nested objects, naming the path in errors.
+    fn as_object_get_path_result<'a>(
+        &'a self,
+        path: &[&str],
+    ) -> anyhow::Result<&'a sonic_rs::Value>;
+    fn as_str_result(&self) -> anyhow::Result<&str>;
+}
+
+impl SonicRsValueExtensions for sonic_rs::Value {
+    type ObjectType = sonic_rs::Object;
+    fn as_object_get_result<'a>(&'a self, key: &str) -> anyhow::Result<&'a sonic_rs::Value> {
+        let res = self
+            .as_object()
+            .ok_or_else(|| anyhow!("Failed using field in json as an object to get `{key}`!"))?
+            .get(&key)
+            .ok_or_else(|| anyhow!("Missing field `{key}` in json!"))?;
+        Ok(res)
+    }
+
+    fn as_object_get_path_result<'a>(
+        &'a self,
+        path: &[&str],
+    ) -> anyhow::Result<&'a sonic_rs::Value> {
+        let mut res = self;
+        for (i, key) in path.iter().enumerate() {
+            res = res.as_object_get_result(key).map_err(|err| {
+                if i == 0 {
+                    err
+                } else {
+                    anyhow!("{err} (at `{}`)", path[..i].join("."))
+                }
+            })?;
+        }
+        Ok(res)
+    }
+
+    fn as_str_result(&self) -> anyhow::Result<&str> {
+        let res = self
+            .as_str()
+            .ok_or_else(|| anyhow!("Failed using field in json as a string!"))?;
+        Ok(res)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RecentEntryType {
+    File,
+    Dir,
+}
+
+#[derive(Debug, Clone)]
+struct RecentEntry<'a> {
+    t: RecentEntryType,
+    val: &'a str,
+}
@@ -1,41 +1,313 @@
-use anyhow::anyhow;
-use clap::{Parser, Subcommand, ValueEnum};
+mod config;
+
+use anyhow::Context;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use codep::{
+    AllOptions, ControlChars, DEFAULT_FRECENCY_HALFLIFE_DAYS, Dedupe, DisplayInfo, DisplayInfoHint,
+    Editor, Filter, FirstOnly, HistoryOptions, HistoryOrder, MatchMode, OrphanedStorage,
+    PathPattern, Profile, RecentOptions, RecentOrder, Record, RecordKind, RecordSource, Sink,
+    SortKey, Stats, WorkspacesOptions, all_entries, cached_records, get_default_config_root,
+    get_display_string_from_remote_type, get_executable_name_from_editor, get_history_path,
+    get_icon_name, get_nerd_glyph, get_portable_config_root, get_profile_user_dir,
+    get_state_db_path, get_storage_json_path, get_user_dir, get_workspace_storage_path,
+    history_entries, local_path_from_uri_path, normalize_uri, orphaned_workspace_storage,
+    parse_date, profiles, recent_entries, stats, workspace_entries,
+};
+use config::Config;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use sonic_rs::{JsonContainerTrait, JsonValueTrait};
 use std::{
-    fmt::{Debug, Display},
-    fs::{self, DirEntry, File},
-    io::{BufReader, Read},
+    borrow::Cow,
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    fmt::{self, Write as _},
+    fs,
+    io::{self, BufWriter, IsTerminal, Write},
     path::{Path, PathBuf},
-    time::{Duration, SystemTime},
+    process::{self, ExitStatus},
+    time::SystemTime,
 };
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 #[command(propagate_version = true)]
 struct Args {
-    #[arg(short, long)]
-    config_root: Option<PathBuf>,
+    /// Directory with the editor's `User` directory. Takes precedence over `--portable`,
+    /// which takes precedence over the default for the editor. Can be given several times,
+    /// or comma separated, to merge the entries of all of them.
+    #[arg(short, long, env = "CODEP_CONFIG_ROOT", value_delimiter = ',')]
+    config_root: Vec<PathBuf>,
+
+    /// Install directory of a portable editor, read from `<INSTALL_DIR>/data/user-data`.
+    #[arg(long, value_name = "INSTALL_DIR")]
+    portable: Option<PathBuf>,
 
+    /// Terminate entries with a NUL instead of a newline, for `xargs -0` or `fzf --read0`.
     #[arg[short = '0', long]]
     null_terminated: bool,
 
+    /// Terminator of entries instead of a newline. Understands `\n`, `\t`, `\0` and `\\`.
+    #[arg(long, value_name = "STR", value_parser = unescape_separator, conflicts_with = "null_terminated")]
+    output_separator: Option<String>,
+
     #[arg[short = 'p', long]]
     use_pango_markup: bool,
 
+    /// Color the displayed part of entries by their kind. `auto` colors only when stdout is
+    /// a terminal.
+    #[arg(
+        long,
+        value_name = "WHEN",
+        default_value_t,
+        value_enum,
+        conflicts_with = "use_pango_markup"
+    )]
+    color: ColorChoice,
+
     #[arg[short, long]]
     all: bool,
 
+    /// Only collect entries of this kind. Wins over `--all`, which turns on every kind,
+    /// which in turn wins over the `--with-*` flags of the subcommands.
+    #[arg(long, value_enum)]
+    only: Option<Only>,
+
+    /// Output format. The json formats ignore `--null-terminated`.
+    #[arg(long, default_value_t, value_enum, env = "CODEP_FORMAT")]
+    format: OutputFormat,
+
+    /// Indent the output of `--format json`.
+    #[arg(long)]
+    json_pretty: bool,
+
+    /// Editor to read the storage of and to open entries with. Its default config root is
+    /// used unless `--config-root` or `--portable` is given.
+    #[arg(
+        short,
+        long,
+        visible_alias = "variant",
+        default_value_t,
+        value_enum,
+        env = "CODEP_EDITOR"
+    )]
+    editor: Editor,
+
+    /// Drop local entries whose path doesn't exist anymore. Remotes are always kept.
+    #[arg(long, visible_alias = "existing-only")]
+    skip_missing: bool,
+
+    /// Abbreviate a leading home directory to `~` in displayed paths. The JSON `path` stays
+    /// absolute.
+    #[arg(long)]
+    tilde: bool,
+
+    /// Show only the last segment of paths, or the name of the `.code-workspace` file for
+    /// its folders. The value column stays absolute, and root paths are shown as they are.
+    #[arg(long, visible_alias = "basename")]
+    basename_only: bool,
+
+    /// Leave out the ` (remote type|addition)` hint after the display strings of remotes.
+    #[arg(long, visible_alias = "no-remotes-hint")]
+    plain_remotes: bool,
+
+    /// Custom line format with the placeholders `{path}`, `{display}`, `{name}`,
+    /// `{remote_type}`, `{addition}`, `{mtime}`, `{kind}` and `{source}`. `{{` and `}}`
+    /// are literal braces.
+    #[arg(long)]
+    template: Option<String>,
+
+    /// Stop after printing this many entries. Unlike `--limit`, which caps how many storage
+    /// directories are read, this caps the printed entries and stops reading early where the
+    /// order allows.
+    #[arg(long, value_name = "N")]
+    first_only: Option<usize>,
+
+    /// Reorder the collected entries of any subcommand. Not to be confused with the
+    /// `--sort` of `workspaces` and `history`, given after the subcommand. Recent entries
+    /// have no mtime and keep their order with `mtime-*`.
+    #[arg(long, value_enum)]
+    sort: Option<EntrySort>,
+
+    /// Print the collected entries of any subcommand in reverse, after `--sort`.
+    #[arg(long)]
+    reverse: bool,
+
+    /// Only keep workspace and history entries modified since this date (`YYYY-MM-DD` or
+    /// RFC 3339). Combines with `--max-age-days` and `--after` of the subcommands. `all`
+    /// also drops recent entries without a timestamp.
+    #[arg(long, value_parser = parse_date_arg)]
+    since: Option<SystemTime>,
+
+    /// Only keep workspace and history entries modified before this date, like `--since`.
+    #[arg(long, value_parser = parse_date_arg)]
+    until: Option<SystemTime>,
+
+    /// Print every location only once, like `--dedupe` for every subcommand. Paths are
+    /// compared without a trailing slash.
+    #[arg(long)]
+    unique: bool,
+
+    /// Only print the number of entries.
+    #[arg(long)]
+    count_only: bool,
+
+    /// Show local paths under this directory relative to it. The value column stays absolute.
+    #[arg(long)]
+    relative_to: Option<PathBuf>,
+
+    /// Trim trailing slashes (and lowercase drive letters on Windows) in printed values.
+    #[arg(long)]
+    normalize: bool,
+
+    /// Prepend an icon name column to every entry with a display string.
+    #[arg(long)]
+    with_icon: bool,
+
+    /// Icon names overriding the defaults, keyed by remote type or `file`, `dir` and
+    /// `remote`. Only settable in the config file.
+    #[arg(skip)]
+    icons: HashMap<String, String>,
+
+    /// Prepend a Nerd Font glyph for the entry type to every display string.
+    #[arg(long = "icons")]
+    nerd_icons: bool,
+
+    /// Glyphs overriding the defaults of `--icons`, keyed like the icon names. Only
+    /// settable in the config file.
+    #[arg(skip)]
+    nerd_glyphs: HashMap<String, String>,
+
+    /// Separator between the columns of entries with a display string. Occurrences inside
+    /// the columns are replaced.
+    #[arg(long, default_value = "\t", hide_default_value = true)]
+    field_separator: String,
+
+    /// Label of a remote type in display strings, like `ssh-remote=SSH`. Can be repeated.
+    #[arg(long = "remote-label", value_name = "TYPE=LABEL", value_parser = parse_remote_label)]
+    remote_labels: Vec<(String, String)>,
+
+    /// Print local paths with backslashes as separators. Always on for Windows.
+    #[arg(long)]
+    windows_paths: bool,
+
+    /// What to do with tabs, newlines, carriage returns and NULs in paths and display
+    /// strings.
+    #[arg(long, default_value_t, value_enum)]
+    control_chars: ControlChars,
+
+    /// Home directory for `--tilde` and `~/` in patterns and `--relative-to`, instead of
+    /// the one of the current user.
+    #[arg(long, value_name = "DIR")]
+    home: Option<PathBuf>,
+
+    /// Only keep entries whose path matches. Glob, or regex when wrapped in `/.../`.
+    #[arg(long)]
+    include: Vec<PathPattern>,
+
+    /// Drop entries whose path matches. Glob, or regex when wrapped in `/.../`.
+    #[arg(long)]
+    exclude: Vec<PathPattern>,
+
+    /// Only keep entries whose path contains the string, see `--match-mode`. Can be
+    /// repeated to keep entries matching any of them.
+    #[arg(long = "match", value_name = "STRING")]
+    matches: Vec<String>,
+
+    /// How `--match` compares.
+    #[arg(long, default_value_t, value_enum)]
+    match_mode: MatchMode,
+
+    /// Only keep entries whose path matches the regex. Can be repeated, entries have to
+    /// match all of them.
+    #[arg(long, value_name = "REGEX")]
+    filter_regex: Vec<Regex>,
+
+    /// Drop entries whose path matches the regex. Can be repeated.
+    #[arg(long, value_name = "REGEX")]
+    exclude_regex: Vec<Regex>,
+
+    /// Report skipped and unreadable entries on stderr. `-vv` also reports the files read
+    /// and how many entries each produced. Without either, `RUST_LOG` is honored.
+    #[arg(short, long, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Don't print any warnings.
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Cache the entries of each subcommand and config root in
+    /// `$XDG_CACHE_HOME/code-pick-recent` and print them from there until the storage
+    /// changes. Not used with `--max-age-days` or `--skip-missing`.
+    #[arg(long, env = "CODEP_CACHE", value_parser = clap::builder::BoolishValueParser::new())]
+    cache: bool,
+
+    /// Don't use the cache, even if `--cache`, `CODEP_CACHE` or the config file enable it.
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Ignore the config file, `code-pick-recent.toml` next to the config root or
+    /// `$XDG_CONFIG_HOME/code-pick-recent/config.toml`.
+    #[arg(long)]
+    no_config: bool,
+
+    /// Read the storage of the profile with this name instead of the default profile.
+    #[arg(long)]
+    profile: Option<String>,
+
+    /// Print the names of the available profiles.
+    #[arg(long)]
+    list_profiles: bool,
+
+    /// Print the config root that would be read and exit.
+    #[arg(long)]
+    print_config_root: bool,
+
+    /// Print the files and directories the subcommand would read and exit.
+    #[arg(long)]
+    print_resolved_paths: bool,
+
     #[command(subcommand)]
-    command: Command,
+    command: Option<Command>,
 }
 
-fn get_default_config_root() -> PathBuf {
-    dirs::config_dir().expect("No config path!").join("Code")
+#[derive(Clone, Debug, Subcommand)]
+enum Command {
+    #[command(flatten)]
+    List(ListCommand),
+    /// Open a path or URI, as printed by the other subcommands, in the editor. Exits with
+    /// the exit code of the editor.
+    Open {
+        target: String,
+        /// Executable to launch instead of the one of `--editor`.
+        #[arg(long, env = "CODEP_EXECUTABLE")]
+        executable: Option<PathBuf>,
+    },
+    /// Print totals of the entries found in every source.
+    Stats,
+    /// Remove `workspaceStorage` directories of local folders that don't exist anymore.
+    Clean {
+        /// Only print what would be removed.
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Also remove directories that weren't modified in this many days. Remote
+        /// workspaces are never removed.
+        #[arg(short = 'M', long)]
+        max_age_days: Option<u32>,
+    },
+    /// Print the number of entries another subcommand would list, like `--count-only`.
+    Count {
+        #[command(subcommand)]
+        command: ListCommand,
+    },
+    /// Print a completion script for `shell`.
+    #[command(hide = true)]
+    Completions { shell: clap_complete::Shell },
 }
 
+/// Subcommands listing entries.
 #[derive(Clone, Debug, Subcommand)]
-enum Command {
+enum ListCommand {
     Recent {
         #[arg(short = 'w', long)]
         with_files: bool,
@@ -45,25 +317,47 @@ enum Command {
         all: bool,
         #[arg(short = 'd', long, default_value_t, value_enum)]
         order: RecentOrder,
-    },
-    Workspaces {
-        #[arg(short = 'W', long)]
-        with_dirs: bool,
-        #[arg(short = 'r', long)]
-        with_remotes: bool,
-        #[arg(short, long)]
-        all: bool,
-
-        #[arg(short = 'D', long)]
-        create_display_strings: bool,
 
+        /// Compares against the modification time of local paths. Paths that can't be
+        /// inspected (e.g. remotes) are kept. Nothing is listed if the recently opened list
+        /// itself is older.
         #[arg(short = 'M', long)]
         max_age_days: Option<u32>,
 
+        /// Maximum number of entries, counted after `--order` was applied.
         #[arg(short, long)]
         limit: Option<usize>,
     },
+    Workspaces {
+        #[command(flatten)]
+        folder: FolderArgs,
+    },
     History {
+        #[command(flatten)]
+        folder: FolderArgs,
+
+        /// List the local files. The history only tracks files, so `--with-dirs` does the
+        /// same.
+        #[arg(short = 'w', long)]
+        with_files: bool,
+
+        #[arg(short = 'd', long, default_value_t, value_enum)]
+        order: HistoryOrder,
+
+        /// Days after which the recency weight of an entry is halved when ordering by
+        /// frecency.
+        #[arg(long, default_value_t = DEFAULT_FRECENCY_HALFLIFE_DAYS)]
+        frecency_halflife_days: f64,
+
+        /// Keep every entry of a file instead of only the newest one. The opposite of
+        /// `--dedupe`.
+        #[arg(long, visible_alias = "no-dedup", conflicts_with = "dedupe")]
+        no_dedupe: bool,
+    },
+    /// Merge the recent, workspace and history entries, newest first.
+    All {
+        #[arg(short = 'w', long)]
+        with_files: bool,
         #[arg(short = 'W', long)]
         with_dirs: bool,
         #[arg(short = 'r', long)]
@@ -71,6 +365,7 @@ enum Command {
         #[arg(short, long)]
         all: bool,
 
+        /// Also adds the source of every entry as a column.
         #[arg(short = 'D', long)]
         create_display_strings: bool,
 
@@ -82,64 +377,622 @@ enum Command {
     },
 }
 
-#[derive(Debug, Clone, Default, ValueEnum, PartialEq, Eq, Serialize, Deserialize)]
+/// Options shared by the `workspaces` and `history` subcommands.
+#[derive(Clone, Debug, clap::Args)]
+struct FolderArgs {
+    #[arg(short = 'W', long)]
+    with_dirs: bool,
+    #[arg(short = 'r', long)]
+    with_remotes: bool,
+    #[arg(short, long)]
+    all: bool,
+
+    #[arg(short = 'D', long)]
+    create_display_strings: bool,
+
+    #[arg(short = 'M', long)]
+    max_age_days: Option<u32>,
+
+    /// Only keep entries modified after this date (`YYYY-MM-DD` or RFC 3339).
+    #[arg(long, value_parser = parse_date_arg)]
+    after: Option<SystemTime>,
+
+    /// Only keep entries modified before this date (`YYYY-MM-DD` or RFC 3339).
+    #[arg(long, value_parser = parse_date_arg)]
+    before: Option<SystemTime>,
+
+    #[arg(short, long)]
+    limit: Option<usize>,
+
+    #[arg(long, default_value_t, value_enum)]
+    sort: SortKey,
+
+    #[arg(long)]
+    reverse: bool,
+
+    #[arg(long)]
+    dedupe: bool,
+
+    /// Append the last modification time as another column.
+    #[arg(long)]
+    with_timestamp: bool,
+
+    /// Short for `--timestamp-format epoch`.
+    #[arg(long, requires = "with_timestamp")]
+    timestamp_epoch: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        requires = "with_timestamp",
+        conflicts_with = "timestamp_epoch"
+    )]
+    timestamp_format: Option<TimestampFormat>,
+
+    /// Append how long ago entries were modified, like `3 days ago`. Short for
+    /// `--with-timestamp --timestamp-format relative`.
+    #[arg(long, conflicts_with_all = ["timestamp_epoch", "timestamp_format"])]
+    relative_time: bool,
+}
+
+impl FolderArgs {
+    fn configure_output(&self, output: &mut Output) {
+        if self.relative_time {
+            output.show_timestamps(TimestampFormat::Relative);
+        } else if self.with_timestamp {
+            output.show_timestamps(self.timestamp_format.unwrap_or(if self.timestamp_epoch {
+                TimestampFormat::Epoch
+            } else {
+                TimestampFormat::Rfc3339
+            }));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Only {
+    Files,
+    Dirs,
+    Remotes,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum EntrySort {
+    /// Newest first.
+    MtimeDesc,
+    MtimeAsc,
+    /// By the shown path, without `file://` or the remote authority.
+    Alpha,
+    AlphaDesc,
+    /// Shortest shown path first.
+    PathLength,
+}
+
+impl EntrySort {
+    fn is_mtime(self) -> bool {
+        matches!(self, EntrySort::MtimeDesc | EntrySort::MtimeAsc)
+    }
+}
+
+/// Sorts stably, so entries comparing equal keep the order of the subcommand. Entries
+/// without an mtime come last with `mtime-*`. `reverse` flips the result.
+fn sort_entries(mut records: Vec<Record>, sort: Option<EntrySort>, reverse: bool) -> Vec<Record> {
+    match sort {
+        None => {}
+        Some(EntrySort::MtimeDesc) => records.sort_by(|r1, r2| {
+            r2.last_modified_at
+                .is_some()
+                .cmp(&r1.last_modified_at.is_some())
+                .then(r2.last_modified_at.cmp(&r1.last_modified_at))
+        }),
+        Some(EntrySort::MtimeAsc) => records.sort_by(|r1, r2| {
+            r2.last_modified_at
+                .is_some()
+                .cmp(&r1.last_modified_at.is_some())
+                .then(r1.last_modified_at.cmp(&r2.last_modified_at))
+        }),
+        Some(EntrySort::Alpha) => records.sort_by_cached_key(|r| sort_path(r).into_owned()),
+        Some(EntrySort::AlphaDesc) => {
+            records.sort_by_cached_key(|r| Reverse(sort_path(r).into_owned()))
+        }
+        Some(EntrySort::PathLength) => records.sort_by_cached_key(|r| sort_path(r).chars().count()),
+    }
+    if reverse {
+        records.reverse();
+    }
+    records
+}
+
+/// The path as shown: the display string without its hint, or the path of the uri.
+fn sort_path(record: &Record) -> Cow<'_, str> {
+    if let Some(display) = &record.display {
+        return Cow::Borrowed(&display.val);
+    }
+    if let Some(path) = record.path.strip_prefix("file://") {
+        return local_path_from_uri_path(path, false);
+    }
+    match record.path.strip_prefix("vscode-remote://") {
+        Some(rest) => Cow::Borrowed(rest.find('/').map_or("", |i| &rest[i..])),
+        None => Cow::Borrowed(&record.path),
+    }
+}
+
+/// The earlier of two optional upper bounds.
+fn earliest(t1: Option<SystemTime>, t2: Option<SystemTime>) -> Option<SystemTime> {
+    match (t1, t2) {
+        (Some(t1), Some(t2)) => Some(t1.min(t2)),
+        (t1, t2) => t1.or(t2),
+    }
+}
+
+/// Collects the entries of every user dir. Entries of several are merged newest first if
+/// `by_mtime` and kept in the order of the config roots otherwise, dropping later duplicates.
+fn collect_entries(
+    user_dirs: &[PathBuf],
+    by_mtime: bool,
+    cache: Option<EntryCache>,
+    collect_uncached: impl Fn(&Path, &mut Vec<Record>) -> anyhow::Result<()>,
+) -> anyhow::Result<Vec<Record>> {
+    let collect = |user_dir: &Path, records: &mut Vec<Record>| match &cache {
+        Some(cache) => {
+            let cached = cache.records(user_dir, |records| collect_uncached(user_dir, records))?;
+            records.extend(cached);
+            Ok(())
+        }
+        None => collect_uncached(user_dir, records),
+    };
+    let mut records = Vec::new();
+    if let [user_dir] = user_dirs {
+        collect(user_dir, &mut records)?;
+        return Ok(records);
+    }
+    for user_dir in user_dirs {
+        collect(user_dir, &mut records)?;
+    }
+    if by_mtime {
+        records.sort_by(|r1, r2| r2.last_modified_at.cmp(&r1.last_modified_at));
+    }
+    let mut seen = HashSet::new();
+    records.retain(|record| seen.insert(normalize_uri(&record.path).into_owned()));
+    Ok(records)
+}
+
+/// Kinds of entries to collect, see `--only`.
+#[derive(Debug, Clone, Copy)]
+struct Selection {
+    files: bool,
+    dirs: bool,
+    remotes: bool,
+}
+
+impl Selection {
+    fn new(only: Option<Only>, all: bool, files: bool, dirs: bool, remotes: bool) -> Self {
+        match only {
+            Some(only) => Self {
+                files: only == Only::Files,
+                dirs: only == Only::Dirs,
+                remotes: only == Only::Remotes,
+            },
+            None => Self {
+                files: all || files,
+                dirs: all || dirs,
+                remotes: all || remotes,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum OutputFormat {
+    #[default]
+    Plain,
+    Json,
+    /// One json object per line.
+    #[value(alias = "ndjson")]
+    #[serde(alias = "ndjson")]
+    JsonLines,
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum, PartialEq, Eq, Deserialize)]
 #[serde(rename_all = "kebab-case")]
-enum RecentOrder {
+enum ColorChoice {
     #[default]
-    Unchanged,
-    FilesFirst,
-    DirsFirst,
+    Auto,
+    Always,
+    Never,
 }
 
 fn main() -> anyhow::Result<()> {
     let Args {
         config_root,
+        portable,
         all: global_all,
+        only,
         null_terminated,
+        output_separator,
         use_pango_markup,
+        color,
+        format,
+        json_pretty,
+        editor,
+        skip_missing,
+        tilde,
+        first_only,
+        sort: entry_sort,
+        reverse: reverse_entries,
+        since,
+        until,
+        unique,
+        count_only,
+        relative_to,
+        basename_only,
+        plain_remotes,
+        template,
+        normalize,
+        with_icon,
+        icons,
+        nerd_icons,
+        nerd_glyphs,
+        remote_labels,
+        field_separator,
+        windows_paths,
+        control_chars,
+        home,
+        include,
+        exclude,
+        matches,
+        match_mode,
+        filter_regex,
+        exclude_regex,
+        profile,
+        list_profiles,
+        print_config_root,
+        print_resolved_paths,
+        verbose,
+        quiet,
+        cache,
+        no_cache,
+        no_config: _,
         command,
-    } = Args::parse();
-    let config_root = config_root
-        .or_else(|| std::env::var("CODEP_CONFIG_ROOT").ok().map(PathBuf::from))
-        .unwrap_or_else(get_default_config_root);
+    } = parse_args()?;
+    init_logger(verbose, quiet);
+    let config_roots = match (config_root, portable) {
+        (config_roots, _) if !config_roots.is_empty() => config_roots,
+        (_, Some(install_dir)) => vec![get_portable_config_root(&install_dir)?],
+        (_, None) => vec![get_default_config_root(editor)?],
+    };
+    if print_config_root {
+        for config_root in &config_roots {
+            println!("{}", std::path::absolute(config_root)?.display());
+        }
+        return Ok(());
+    }
+    if list_profiles {
+        for config_root in &config_roots {
+            for Profile { name, .. } in profiles(config_root)? {
+                println!("{name}");
+            }
+        }
+        return Ok(());
+    }
+    if since.zip(until).is_some_and(|(since, until)| since > until) {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "`--since` is later than `--until`",
+            )
+            .exit();
+    }
+    if json_pretty && format != OutputFormat::Json {
+        let message = if format == OutputFormat::JsonLines {
+            "`--json-pretty` would break the line-delimited output of `--format json-lines`"
+        } else {
+            "`--json-pretty` only works with `--format json`"
+        };
+        Args::command()
+            .error(clap::error::ErrorKind::ArgumentConflict, message)
+            .exit();
+    }
+    let user_dirs = config_roots
+        .iter()
+        .map(|config_root| match &profile {
+            Some(profile) => get_profile_user_dir(config_root, profile),
+            None => Ok(get_user_dir(config_root)),
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if print_resolved_paths {
+        for user_dir in &user_dirs {
+            for path in resolved_paths(&std::path::absolute(user_dir)?, command.as_ref()) {
+                println!("{}", path.display());
+            }
+        }
+        return Ok(());
+    }
+    let Some(command) = command else {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::MissingSubcommand,
+                "A subcommand is required unless `--list-profiles` or `--print-*` is given",
+            )
+            .exit();
+    };
 
-    match command {
-        Command::Recent {
+    let home = home.or_else(dirs::home_dir);
+    let mut output = Output::new(format, null_terminated, use_pango_markup);
+    output.set_control_chars(control_chars);
+    let color = match color {
+        ColorChoice::Always => true,
+        ColorChoice::Auto => io::stdout().is_terminal(),
+        ColorChoice::Never => false,
+    };
+    // Markup from the config file wins over colors from it.
+    if color && !use_pango_markup {
+        output.enable_colors();
+    }
+    if tilde {
+        if let Some(home) = &home {
+            output.show_tilde(home.clone());
+        }
+    }
+    if json_pretty {
+        output.json_pretty();
+    }
+    if normalize {
+        output.enable_normalize();
+    }
+    if let Some(relative_to) = relative_to {
+        let relative_to = match (relative_to.strip_prefix("~"), &home) {
+            (Ok(rest), Some(home)) => home.join(rest),
+            _ => relative_to,
+        };
+        output.show_relative_to(relative_to);
+    }
+    if basename_only {
+        output.show_basename_only();
+    }
+    if plain_remotes {
+        output.hide_remote_hints();
+    }
+    if let Some(template) = template {
+        output.set_template(template);
+    }
+    if with_icon {
+        output.show_icons(icons);
+    }
+    if nerd_icons {
+        output.show_glyphs(nerd_glyphs);
+    }
+    output.set_field_separator(field_separator);
+    if let Some(output_separator) = output_separator {
+        output.set_output_separator(output_separator);
+    }
+    if !remote_labels.is_empty() {
+        output.set_remote_labels(remote_labels.into_iter().collect());
+    }
+    let expand_home = |patterns: Vec<PathPattern>| match &home {
+        Some(home) => patterns
+            .into_iter()
+            .map(|pattern| pattern.expand_home(home))
+            .collect::<anyhow::Result<Vec<_>>>(),
+        None => Ok(patterns),
+    };
+    let mut exclude = expand_home(exclude)?;
+    exclude.extend(exclude_regex.into_iter().map(PathPattern::Regex));
+    let filter = Filter {
+        skip_missing,
+        include: expand_home(include)?,
+        exclude,
+        matches: matches
+            .into_iter()
+            .map(|s| match (s.strip_prefix("~/"), &home) {
+                (Some(rest), Some(home)) => home.join(rest).to_string_lossy().into_owned(),
+                _ => s,
+            })
+            .collect(),
+        match_mode,
+        required: filter_regex,
+    };
+
+    // Caps what the storage is read for, where the printed order is the collected one.
+    let read_first_only = first_only
+        .filter(|_| !(unique || reverse_entries) && entry_sort.is_none() && user_dirs.len() == 1);
+    let (command, count_only) = match command {
+        Command::List(command) => (command, count_only),
+        Command::Count { command } => (command, true),
+        Command::Open { target, executable } => {
+            let executable = executable
+                .unwrap_or_else(|| PathBuf::from(get_executable_name_from_editor(editor)));
+            // Remote history entries are files, everything else remote is a folder.
+            let mut files = Vec::new();
+            if target.starts_with("vscode-remote://") {
+                let options = HistoryOptions {
+                    with_remotes: true,
+                    ..Default::default()
+                };
+                for user_dir in &user_dirs {
+                    let mut records = Vec::new();
+                    match history_entries(user_dir, &options, &Filter::default(), &mut records) {
+                        Ok(()) => files.extend(records.into_iter().map(|r| r.path)),
+                        Err(err) => log::debug!("Failed reading history entries! {err}"),
+                    }
+                }
+            }
+            let status = open_in_editor(&executable, &target, &files)?;
+            // Killed by a signal if there is no code.
+            process::exit(status.code().unwrap_or(1));
+        }
+        Command::Stats => {
+            let mut total = Stats::default();
+            for user_dir in &user_dirs {
+                total.add(stats(user_dir, &filter)?);
+            }
+            return print_stats(&total, format, json_pretty);
+        }
+        Command::Clean {
+            dry_run,
+            max_age_days,
+        } => {
+            for user_dir in &user_dirs {
+                clean_workspace_storage(user_dir, dry_run, max_age_days)?;
+            }
+            return Ok(());
+        }
+        Command::Completions { shell } => {
+            let mut command = Args::command();
+            let name = command.get_name().to_owned();
+            clap_complete::generate(shell, &mut command, name, &mut std::io::stdout());
+            return Ok(());
+        }
+    };
+    let cache_dir = (cache && !no_cache)
+        .then(dirs::cache_dir)
+        .flatten()
+        .map(|dir| dir.join("code-pick-recent"));
+    // Entries depending on the time of the run or on other files than the storage are
+    // read every time.
+    let entry_cache = |subcommand,
+                       sources: fn(&Path) -> Vec<PathBuf>,
+                       options: &dyn fmt::Debug,
+                       max_age_days: Option<u32>| {
+        Some(EntryCache {
+            dir: cache_dir.clone()?,
+            subcommand,
+            key: format!("{options:?} {filter:?}"),
+            sources,
+        })
+        .filter(|_| max_age_days.is_none() && !filter.skip_missing)
+    };
+    // Entries are sorted across all user dirs before they reach the sinks.
+    let mut dedupe_output = unique;
+    let records = match command {
+        ListCommand::Recent {
             with_files,
             with_dirs,
             all,
             order,
+            max_age_days,
+            limit,
         } => {
             let all = global_all || all;
-            collect_items_in_menu_settings(
-                config_root,
-                null_terminated,
-                all || with_files,
-                all || with_dirs,
+            let selection = Selection::new(only, all, with_files, with_dirs, false);
+            let options = RecentOptions {
+                with_files: selection.files,
+                with_dirs: selection.dirs,
                 order,
-            )?;
+                max_age_days,
+                limit,
+                windows_paths,
+                control_chars,
+            };
+            let cache = entry_cache("recent", recent_sources, &options, max_age_days);
+            let records = collect_entries(&user_dirs, false, cache, |user_dir, records| {
+                recent_entries(user_dir, &options, &filter, records)
+            })?;
+            sort_entries(
+                records,
+                entry_sort.filter(|sort| !sort.is_mtime()),
+                reverse_entries,
+            )
         }
-        Command::Workspaces {
-            with_dirs,
-            with_remotes,
-            all,
-            create_display_strings,
-            max_age_days,
-            limit,
-        } => {
+        ListCommand::Workspaces { folder } => {
+            folder.configure_output(&mut output);
+            let FolderArgs {
+                with_dirs,
+                with_remotes,
+                all,
+                create_display_strings,
+                max_age_days,
+                after,
+                before,
+                limit,
+                sort,
+                reverse,
+                dedupe,
+                ..
+            } = folder;
+            dedupe_output |= dedupe;
             let all = global_all || all;
-            collect_items_in_workspaces(
-                config_root,
+            let selection = Selection::new(only, all, false, with_dirs, with_remotes);
+            let options = WorkspacesOptions {
+                with_dirs: selection.dirs,
+                with_remotes: selection.remotes,
+                create_display_strings: create_display_strings || format != OutputFormat::Plain,
                 max_age_days,
+                after: after.max(since),
+                before: earliest(before, until),
                 limit,
-                all || with_dirs,
-                all || with_remotes,
-                null_terminated,
+                // Duplicates are only dropped while printing.
+                first_only: read_first_only.filter(|_| !dedupe),
+                sort,
+                reverse,
+                windows_paths,
+                control_chars,
+                keep_placeholders: all,
+            };
+            let by_mtime = sort == SortKey::Mtime && !reverse;
+            let cache = entry_cache("workspaces", workspace_sources, &options, max_age_days);
+            let records = collect_entries(&user_dirs, by_mtime, cache, |user_dir, records| {
+                workspace_entries(user_dir, &options, &filter, records)
+            })?;
+            sort_entries(records, entry_sort, reverse_entries)
+        }
+        ListCommand::History {
+            folder,
+            with_files,
+            order,
+            frecency_halflife_days,
+            no_dedupe,
+        } => {
+            folder.configure_output(&mut output);
+            let FolderArgs {
+                with_dirs,
+                with_remotes,
+                all,
                 create_display_strings,
-                use_pango_markup,
-            )?;
+                max_age_days,
+                after,
+                before,
+                limit,
+                sort,
+                reverse,
+                dedupe,
+                ..
+            } = folder;
+            dedupe_output |= dedupe;
+            let all = global_all || all;
+            // The local history only tracks files, `--with-dirs` is kept for older scripts.
+            let selection = Selection::new(only, all, with_files || with_dirs, false, with_remotes);
+            let options = HistoryOptions {
+                with_files: selection.files,
+                with_remotes: selection.remotes,
+                create_display_strings: create_display_strings || format != OutputFormat::Plain,
+                max_age_days,
+                after: after.max(since),
+                before: earliest(before, until),
+                limit,
+                // Duplicates are only dropped while printing.
+                first_only: read_first_only.filter(|_| !dedupe),
+                order,
+                frecency_halflife_days,
+                dedupe: !no_dedupe,
+                sort,
+                reverse,
+                windows_paths,
+                control_chars,
+                keep_placeholders: all,
+            };
+            let by_mtime = order == HistoryOrder::Mtime && sort == SortKey::Mtime && !reverse;
+            let cache = entry_cache("history", history_sources, &options, max_age_days);
+            let records = collect_entries(&user_dirs, by_mtime, cache, |user_dir, records| {
+                history_entries(user_dir, &options, &filter, records)
+            })?;
+            sort_entries(records, entry_sort, reverse_entries)
         }
-        Command::History {
+        ListCommand::All {
+            with_files,
             with_dirs,
             with_remotes,
             all,
@@ -147,531 +1000,920 @@ fn main() -> anyhow::Result<()> {
             max_age_days,
             limit,
         } => {
+            if create_display_strings {
+                output.show_source();
+            }
             let all = global_all || all;
-            collect_items_in_history(
-                config_root,
-                limit,
+            let selection = Selection::new(only, all, with_files, with_dirs, with_remotes);
+            let options = AllOptions {
+                with_files: selection.files,
+                with_dirs: selection.dirs,
+                with_remotes: selection.remotes,
+                create_display_strings: create_display_strings || format != OutputFormat::Plain,
                 max_age_days,
-                all || with_dirs,
-                all || with_remotes,
-                null_terminated,
-                create_display_strings,
-                use_pango_markup,
-            )?;
+                after: since,
+                before: until,
+                limit,
+                windows_paths,
+                control_chars,
+                keep_placeholders: all,
+            };
+            let cache = entry_cache("all", all_sources, &options, max_age_days);
+            let records = collect_entries(&user_dirs, false, cache, |user_dir, records| {
+                all_entries(user_dir, &options, &filter, records)
+            })?;
+            sort_entries(records, entry_sort, reverse_entries)
         }
+    };
+    let mut sink: Box<dyn Sink> = if count_only {
+        Box::new(Count::default())
+    } else {
+        Box::new(output)
+    };
+    if let Some(first_only) = first_only {
+        sink = Box::new(FirstOnly::new(sink, first_only));
     }
-    Ok(())
+    if dedupe_output {
+        sink = Box::new(Dedupe::new(sink));
+    }
+    sink.emit_all(records)?;
+    sink.finish()
 }
 
-#[allow(clippy::too_many_arguments)]
-fn collect_items_in_workspaces(
-    mut storage_path: PathBuf,
-    max_age_days: Option<u32>,
-    limit: Option<usize>,
-    with_dirs: bool,
-    with_remotes: bool,
-    null_terminated: bool,
-    create_display_strings: bool,
-    use_pango_markup: bool,
-) -> anyhow::Result<()> {
-    storage_path.push("User/workspaceStorage");
-
-    let min_system_time = max_age_days
-        .map(get_min_system_time_from_max_age_days)
-        .transpose()?;
-
-    let mut entries = fs::read_dir(&storage_path)?
-        .filter_map(|entry| match get_data_from_dir_entry(entry) {
-            Err(err) => {
-                eprintln!("Error at: {}", &storage_path.as_os_str().to_string_lossy());
-                eprintln!("Error reading workspace entry! {err}");
-                None
-            }
-            Ok(entry) => {
-                if let Some(min_system_time) = min_system_time {
-                    if entry.last_modified_at < min_system_time {
-                        return None;
-                    }
-                }
-                Some(entry)
-            }
-        })
-        .collect::<Vec<_>>();
+/// Sources read by `command`, all of them without one.
+fn resolved_paths(user_dir: &Path, command: Option<&Command>) -> Vec<PathBuf> {
+    let command = match command {
+        Some(Command::List(command) | Command::Count { command }) => Some(command),
+        Some(Command::Clean { .. }) => return workspace_sources(user_dir),
+        Some(Command::Stats | Command::Open { .. } | Command::Completions { .. }) | None => None,
+    };
+    match command {
+        Some(ListCommand::Recent { .. }) => recent_sources(user_dir),
+        Some(ListCommand::Workspaces { .. }) => workspace_sources(user_dir),
+        Some(ListCommand::History { .. }) => history_sources(user_dir),
+        Some(ListCommand::All { .. }) | None => all_sources(user_dir),
+    }
+}
 
-    entries.sort_by(|e1, e2| e1.last_modified_at.cmp(&e2.last_modified_at).reverse());
+fn recent_sources(user_dir: &Path) -> Vec<PathBuf> {
+    vec![get_state_db_path(user_dir), get_storage_json_path(user_dir)]
+}
 
-    let limit = limit.unwrap_or(usize::MAX);
+fn workspace_sources(user_dir: &Path) -> Vec<PathBuf> {
+    vec![get_workspace_storage_path(user_dir)]
+}
 
-    for FolderEntry { path, .. } in entries.into_iter().take(limit) {
-        let path = path.join("workspace.json");
-        if let Err(err) = digest_workspaces_dir_entry(
-            &path,
-            with_dirs,
-            with_remotes,
-            null_terminated,
-            create_display_strings,
-            use_pango_markup,
-        ) {
-            eprintln!("Error with file: {}", &path.as_os_str().to_string_lossy());
-            eprintln!("Error digesting workspace entry! {err}");
-        }
+fn history_sources(user_dir: &Path) -> Vec<PathBuf> {
+    vec![get_history_path(user_dir)]
+}
+
+fn all_sources(user_dir: &Path) -> Vec<PathBuf> {
+    let mut paths = recent_sources(user_dir);
+    paths.extend(workspace_sources(user_dir));
+    paths.extend(history_sources(user_dir));
+    paths
+}
+
+/// Where the entries of a subcommand are cached, see `--cache`.
+struct EntryCache {
+    dir: PathBuf,
+    subcommand: &'static str,
+    /// Everything besides the storage the entries depend on.
+    key: String,
+    sources: fn(&Path) -> Vec<PathBuf>,
+}
+
+impl EntryCache {
+    /// One file per config root and subcommand, so other options replace it.
+    fn records(
+        &self,
+        user_dir: &Path,
+        collect: impl FnOnce(&mut Vec<Record>) -> anyhow::Result<()>,
+    ) -> anyhow::Result<Vec<Record>> {
+        let name = format!("{}-{:016x}", self.subcommand, {
+            let mut hasher = std::hash::DefaultHasher::new();
+            std::hash::Hash::hash(user_dir, &mut hasher);
+            std::hash::Hasher::finish(&hasher)
+        });
+        let key = format!("{} {}", user_dir.display(), self.key);
+        cached_records(&self.dir, &name, &key, &(self.sources)(user_dir), collect)
     }
-    Ok(())
 }
 
-fn get_min_system_time_from_max_age_days(max_age_days: u32) -> anyhow::Result<SystemTime> {
-    const NUM_SECONDS_IN_DAY: u64 = 86400;
-    let res = SystemTime::now()
-        .checked_sub(Duration::from_secs(
-            (max_age_days as u64) * NUM_SECONDS_IN_DAY,
-        ))
-        .ok_or_else(|| anyhow!("`max-age-days` too big"))?;
+fn unescape_separator(s: &str) -> Result<String, String> {
+    let mut res = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => res.push('\n'),
+            Some('t') => res.push('\t'),
+            Some('0') => res.push('\0'),
+            Some('\\') => res.push('\\'),
+            Some(c) => return Err(format!("Unknown escape `\\{c}`")),
+            None => return Err("Trailing `\\`".to_owned()),
+        }
+    }
     Ok(res)
 }
 
-#[derive(Clone, Debug)]
-struct FolderEntry {
-    path: PathBuf,
-    last_modified_at: SystemTime,
+fn parse_remote_label(s: &str) -> Result<(String, String), String> {
+    let (remote_type, label) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Expected `TYPE=LABEL`, got `{s}`"))?;
+    Ok((remote_type.to_owned(), label.to_owned()))
 }
 
-fn digest_history_dir_entry(
-    path: &Path,
-    null_terminated: bool,
-    with_dirs: bool,
-    with_remotes: bool,
-    create_display_strings: bool,
-    use_pango_markup: bool,
-) -> anyhow::Result<()> {
-    if !fs::exists(path)? {
-        return Ok(());
+fn parse_date_arg(s: &str) -> Result<SystemTime, String> {
+    parse_date(s).map_err(|err| err.to_string())
+}
+
+fn parse_args() -> anyhow::Result<Args> {
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    if !args.no_config {
+        // Only a config root given on the command line or in the environment has one.
+        if let Some(config) = Config::load(args.config_root.first().map(PathBuf::as_path))? {
+            config.apply(&mut args, &matches)?;
+        }
     }
-    let mut file = File::open(path)?;
-    let mut v: Vec<u8> = Vec::new();
-    file.read_to_end(&mut v)?;
-    let value: sonic_rs::Value = sonic_rs::from_slice(&v)?;
+    Ok(args)
+}
 
-    let Ok(field) = value.as_object_get_result("resource") else {
-        return Ok(());
+fn init_logger(verbose: u8, quiet: bool) {
+    let level = match (quiet, verbose) {
+        (true, _) => log::LevelFilter::Off,
+        (false, 0) => log::LevelFilter::Warn,
+        (false, 1) => log::LevelFilter::Debug,
+        (false, _) => log::LevelFilter::Trace,
     };
-    let val = field.as_str_result()?;
-    digest_folder_uri(
-        val,
-        null_terminated,
-        use_pango_markup,
-        with_dirs,
-        with_remotes,
-        create_display_strings,
-    )?;
-    Ok(())
+    let mut builder = env_logger::Builder::new();
+    builder.filter_level(level);
+    if !quiet && verbose == 0 {
+        builder.parse_default_env();
+    }
+    builder
+        .format(|buf, record| {
+            writeln!(
+                buf,
+                "{}: {}",
+                record.level().as_str().to_lowercase(),
+                record.args()
+            )
+        })
+        .init();
 }
 
-fn digest_workspaces_dir_entry(
-    path: &Path,
-    with_dirs: bool,
-    with_remotes: bool,
-    null_terminated: bool,
-    create_display_strings: bool,
-    use_pango_markup: bool,
-) -> anyhow::Result<()> {
-    if !fs::exists(path)? {
-        return Ok(());
+/// Opens an entry as printed by the list subcommands, i.e. with decoded uris. Remote
+/// `files`, like the ones of the history, are opened with `--file-uri`.
+fn open_in_editor(executable: &Path, target: &str, files: &[String]) -> anyhow::Result<ExitStatus> {
+    let mut command = process::Command::new(executable);
+    if let Some(rest) = target.strip_prefix("vscode-remote://") {
+        let is_file = target.ends_with(".code-workspace") || files.iter().any(|f| f == target);
+        let option = if is_file {
+            "--file-uri"
+        } else {
+            "--folder-uri"
+        };
+        command.arg(option).arg(encode_remote_uri(rest));
+    } else {
+        let path = match target.strip_prefix("file://") {
+            Some(path) => local_path_from_uri_path(path, false),
+            None => Cow::Borrowed(target),
+        };
+        let path = path.as_ref();
+        if Path::new(path).is_dir() {
+            command.arg(path);
+        } else {
+            command.arg("--goto").arg(path);
+        }
     }
-    let mut file = File::open(path)?;
-    let mut v: Vec<u8> = Vec::new();
-    file.read_to_end(&mut v)?;
-    let value: sonic_rs::Value = sonic_rs::from_slice(&v)?;
+    command
+        .status()
+        .with_context(|| format!("Failed to launch `{}`!", executable.display()))
+}
+
+/// Percent-encodes the authority and every path segment of a decoded remote uri, given
+/// without its `vscode-remote://`.
+fn encode_remote_uri(rest: &str) -> String {
+    let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+    let path = path
+        .split('/')
+        .map(urlencoding::encode)
+        .collect::<Vec<_>>()
+        .join("/");
+    format!("vscode-remote://{}/{path}", urlencoding::encode(authority))
+}
 
-    let Ok(field) = value.as_object_get_result("folder") else {
+fn print_stats(stats: &Stats, format: OutputFormat, json_pretty: bool) -> anyhow::Result<()> {
+    if format != OutputFormat::Plain {
+        let json = if json_pretty {
+            sonic_rs::to_string_pretty(stats)?
+        } else {
+            sonic_rs::to_string(stats)?
+        };
+        println!("{json}");
         return Ok(());
-    };
-    let val = field.as_str_result()?;
-    digest_folder_uri(
-        val,
-        null_terminated,
-        use_pango_markup,
-        with_dirs,
-        with_remotes,
-        create_display_strings,
-    )?;
+    }
+    let Stats {
+        workspace_storage_dirs,
+        workspace_entries,
+        history_entries,
+        recent_entries,
+        existing,
+        missing,
+        remote_types,
+    } = stats;
+    println!("workspace storage dirs\t{workspace_storage_dirs}");
+    println!("workspace entries\t{workspace_entries}");
+    println!("history entries\t{history_entries}");
+    println!("recent entries\t{recent_entries}");
+    println!("existing local paths\t{existing}");
+    println!("missing local paths\t{missing}");
+    for (remote_type, count) in remote_types {
+        println!("remote {remote_type}\t{count}");
+    }
     Ok(())
 }
 
-fn digest_folder_uri(
-    val: &str,
-    null_terminated: bool,
-    use_pango_markup: bool,
-    with_dirs: bool,
-    with_remotes: bool,
-    create_display_strings: bool,
+fn clean_workspace_storage(
+    user_dir: &Path,
+    dry_run: bool,
+    max_age_days: Option<u32>,
 ) -> anyhow::Result<()> {
-    let val = urlencoding::decode(val)?;
-
-    let starts_with_file = with_dirs && val.starts_with("file://");
-    let starts_with_remote = with_remotes && val.starts_with("vscode-remote://");
+    let mut total_size = 0;
+    for OrphanedStorage { path, size } in orphaned_workspace_storage(user_dir, max_age_days)? {
+        if !dry_run {
+            fs::remove_dir_all(&path)
+                .with_context(|| format!("Failed removing {}!", path.display()))?;
+        }
+        println!("{}\t{}", path.display(), format_size(size));
+        total_size += size;
+    }
+    if dry_run {
+        println!("Would reclaim {}", format_size(total_size));
+    } else {
+        println!("Reclaimed {}", format_size(total_size));
+    }
+    Ok(())
+}
 
-    if !starts_with_file && !starts_with_remote {
-        return Ok(());
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
     }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = UNITS[0];
+    for next_unit in &UNITS[1..] {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = next_unit;
+    }
+    format!("{size:.1} {unit}")
+}
 
-    let clean_val = val.replace("\t", "").replace("\n", "").replace("\0", "");
+const ANSI_RESET: &str = "\x1b[0m";
 
-    if create_display_strings {
-        print!("{clean_val}\t");
-        if starts_with_file {
-            print!("{}", &val[7..]);
-        } else if starts_with_remote {
-            match extract_folder_name_from_remote_val(&val[16..]) {
-                Err(err) => {
-                    eprintln!("Couldn't parse `vscode-remote` folder-string! ");
-                    eprintln!("{err}");
-                    print!("{clean_val}");
-                }
-                Ok(r) => {
-                    print_display_info(&r, use_pango_markup);
-                }
-            }
+/// SGR parameters of the color of an entry kind.
+fn ansi_color(kind: RecordKind) -> &'static str {
+    match kind {
+        RecordKind::Dir => "1;34",
+        RecordKind::File => "32",
+        RecordKind::Remote => "35",
+    }
+}
+
+fn write_display_info(
+    out: &mut impl fmt::Write,
+    val: &DisplayInfo,
+    use_pango_markup: bool,
+) -> fmt::Result {
+    let escape = |s: &str| -> String {
+        if use_pango_markup {
+            escape_pango_markup(s).into_owned()
         } else {
-            print!("{clean_val}");
+            s.to_owned()
         }
-    } else {
-        print!("{clean_val}");
     };
-
-    if null_terminated {
-        print!("\0");
+    let DisplayInfo { val, hint } = val;
+    write!(out, "{}", escape(val))?;
+    if let Some(hint) = hint {
+        let DisplayInfoHint {
+            remote_type,
+            addition,
+        } = hint;
+        write!(out, " ")?;
+        if use_pango_markup {
+            write!(out, "<small>")?;
+        }
+        write!(out, "({}", escape(remote_type))?;
+        if let Some(addition) = addition {
+            write!(out, "|{}", escape(addition))?;
+        }
+        write!(out, ")")?;
+        if use_pango_markup {
+            write!(out, "</small>")?;
+        }
     }
-    println!();
     Ok(())
 }
 
-fn extract_folder_name_from_remote_val(rest: &str) -> anyhow::Result<DisplayInfo> {
-    let remote_type_end = rest
-        .chars()
-        .position(|c| c == '+')
-        .ok_or_else(|| anyhow!("No space found!"))?;
-    let hex_start = remote_type_end + 1;
-    let hex_end = rest[hex_start..]
-        .chars()
-        .position(|c| c == '/')
-        .ok_or_else(|| anyhow!("No slash found after first space!"))?
-        + hex_start;
-
-    let remote_type = &rest[..remote_type_end];
-    let remote_type = get_display_string_from_remote_type(remote_type);
-
-    // Hex decode
-    let Ok(v) = (hex_start..hex_end)
-        .step_by(2)
-        .map(|i| u8::from_str_radix(&rest[i..i + 2], 16).map(|u| u as char))
-        .collect::<Result<String, _>>()
-    else {
-        return Ok(DisplayInfo {
-            val: rest[hex_start..].to_owned(),
-            hint: Some(DisplayInfoHint {
-                remote_type: remote_type.to_string(),
-                addition: None,
-            }),
-        });
-    };
+fn escape_pango_markup(s: &str) -> Cow<'_, str> {
+    if !s.contains(['&', '<', '>']) {
+        return Cow::Borrowed(s);
+    }
+    Cow::Owned(
+        s.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;"),
+    )
+}
 
-    let info = if let Some((val, addition)) = hint_addition_from_json_slice(&v) {
-        DisplayInfo {
-            val,
-            hint: Some(DisplayInfoHint {
-                remote_type: remote_type.to_string(),
-                addition,
-            }),
-        }
-    } else {
-        DisplayInfo {
-            val: v,
-            hint: Some(DisplayInfoHint {
-                remote_type: remote_type.to_string(),
-                addition: None,
-            }),
+#[derive(Clone, Debug, Serialize)]
+struct JsonRecord {
+    path: String,
+    kind: RecordKind,
+    remote_type: Option<String>,
+    display: Option<String>,
+    last_modified_at: Option<String>,
+    source: RecordSource,
+}
+
+impl From<Record> for JsonRecord {
+    fn from(record: Record) -> Self {
+        let Record {
+            path,
+            kind,
+            remote_type,
+            display,
+            last_modified_at,
+            source,
+            ..
+        } = record;
+        JsonRecord {
+            path,
+            kind,
+            remote_type,
+            display: display.map(|d| d.to_string()),
+            last_modified_at: last_modified_at.map(format_rfc3339),
+            source,
         }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum TimestampFormat {
+    Rfc3339,
+    /// Seconds since 1970.
+    Epoch,
+    /// Like `3 days ago`.
+    Relative,
+}
+
+fn format_timestamp(time: SystemTime, timestamp_format: TimestampFormat) -> String {
+    match timestamp_format {
+        TimestampFormat::Rfc3339 => format_rfc3339(time),
+        TimestampFormat::Epoch => time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+            .to_string(),
+        TimestampFormat::Relative => format_relative_time(time, SystemTime::now()),
+    }
+}
+
+fn format_relative_time(time: SystemTime, now: SystemTime) -> String {
+    const UNITS: [(&str, u64); 4] = [
+        ("week", 7 * 24 * 60 * 60),
+        ("day", 24 * 60 * 60),
+        ("hour", 60 * 60),
+        ("minute", 60),
+    ];
+    // Times in the future come from clock skew.
+    let Ok(age) = now.duration_since(time) else {
+        return "unknown".to_owned();
+    };
+    let secs = age.as_secs();
+    let Some((unit, count)) = UNITS
+        .iter()
+        .map(|(unit, unit_secs)| (unit, secs / unit_secs))
+        .find(|(_, count)| *count > 0)
+    else {
+        return "just now".to_owned();
     };
+    let plural = if count == 1 { "" } else { "s" };
+    format!("{count} {unit}{plural} ago")
+}
 
-    Ok(info)
+fn format_rfc3339(time: SystemTime) -> String {
+    chrono::DateTime::<chrono::Local>::from(time)
+        .to_rfc3339_opts(chrono::SecondsFormat::Secs, false)
 }
 
-fn get_display_string_from_remote_type(remote_type: &str) -> &str {
-    match remote_type {
-        "dev-container" => "Dev Container",
-        "ssh-remote" => "SSH Remote",
-        v => v,
+fn apply_control_chars(record: &mut Record, control_chars: ControlChars) {
+    let apply = |s: &mut String| {
+        if let Cow::Owned(applied) = control_chars.apply(s) {
+            *s = applied;
+        }
+    };
+    if let Some(remote_type) = &mut record.remote_type {
+        apply(remote_type);
     }
+    for_each_display_string(record, apply);
 }
 
-fn hint_addition_from_json_slice(v: &str) -> Option<(String, Option<&'static str>)> {
-    let val: sonic_rs::Value = sonic_rs::from_str(v).ok()?;
-    let obj = val.as_object()?;
-    for path in ["hostPath", "repositoryPath", "volumeName"] {
-        let Some(s) = obj.get(&path) else {
-            continue;
-        };
-        let Some(s) = s.as_str() else {
-            continue;
+/// Keeps the columns of `record` free of `separator`. Uris stay usable by percent-encoding
+/// it, everything else gets a space instead.
+fn escape_field_separator(record: &mut Record, separator: &str) {
+    if separator.is_empty() {
+        return;
+    }
+    if record.path.contains(separator) {
+        let replacement = if record.path.contains("://") {
+            urlencoding::encode(separator)
+        } else {
+            Cow::Borrowed(" ")
         };
-        return Some((s.to_owned(), hint_addition_from_path(path)));
+        record.path = record.path.replace(separator, &replacement);
     }
-    None
+    for_each_display_string(record, |s| {
+        if s.contains(separator) {
+            *s = s.replace(separator, " ");
+        }
+    });
 }
 
-fn hint_addition_from_path(path: &str) -> Option<&'static str> {
-    match path {
-        "hostPath" => None,
-        "repositoryPath" => Some("repository"),
-        "volumeName" => Some("volume"),
-        _ => Some("unknown"),
+fn for_each_display_string(record: &mut Record, mut f: impl FnMut(&mut String)) {
+    if let Some(DisplayInfo { val, hint }) = &mut record.display {
+        f(val);
+        if let Some(DisplayInfoHint {
+            remote_type,
+            addition,
+        }) = hint
+        {
+            f(remote_type);
+            if let Some(addition) = addition {
+                f(addition);
+            }
+        }
     }
 }
 
-fn get_data_from_dir_entry(entry: Result<DirEntry, std::io::Error>) -> anyhow::Result<FolderEntry> {
-    let entry = entry?;
-    if !entry.file_type()?.is_dir() {
-        return Err(anyhow!("Didn't expect file type!"));
+/// The last segment of `path`, ignoring trailing separators. Root paths stay unchanged.
+fn basename(path: &str) -> &str {
+    let trimmed = path.trim_end_matches(['/', '\\']);
+    match trimmed.rsplit(['/', '\\']).next() {
+        Some(name) if !name.is_empty() => name,
+        _ => path,
     }
-    let last_modified_at = entry.metadata()?.modified()?;
-    let path = entry.path();
-    Ok(FolderEntry {
-        path,
-        last_modified_at,
-    })
 }
 
-fn collect_items_in_menu_settings(
-    mut storage_path: PathBuf,
-    null_terminated: bool,
-    with_files: bool,
-    with_dirs: bool,
-    order: RecentOrder,
-) -> anyhow::Result<()> {
-    storage_path.push("User/globalStorage/storage.json");
-    let file = File::open(storage_path)?;
-    let reader = BufReader::new(file);
-    let value: sonic_rs::Value = sonic_rs::from_reader(reader)?;
-    let items = value
-        .as_object_get_result("lastKnownMenubarData")?
-        .as_object_get_result("menus")?
-        .as_object_get_result("File")?
-        .as_object_get_result("items")?
-        .as_array()
-        .ok_or_else(|| anyhow!("Failed using field in json as an array!"))?;
-    let recent = items
-        .iter()
-        .find(|item| {
-            let Ok(id) = item.as_object_get_result("id") else {
-                return false;
-            };
-            let Some(id) = id.as_str() else {
-                return false;
-            };
-            id == "submenuitem.MenubarRecentMenu"
-        })
-        .ok_or_else(|| anyhow!("Didn't find menubar!"))?;
-    let uris = recent
-        .as_object_get_result("submenu")?
-        .as_object_get_result("items")?
-        .as_array()
-        .ok_or_else(|| anyhow!("Failed using field in json as an object!"))?
-        .iter()
-        .filter_map(move |item| {
-            let id = item.as_object_get_result("id").ok()?.as_str()?;
-            let keep_id =
-                with_files && id == "openRecentFile" || with_dirs && id == "openRecentFolder";
-            if !keep_id {
-                return None;
-            }
-            let is_enabled = item.get("enabled").and_then(|s| s.as_bool())?;
-            if !is_enabled {
-                return None;
-            }
-            let val = item
-                .as_object_get_result("uri")
-                .ok()?
-                .as_object_get_result("path")
-                .ok()?
-                .as_str()?;
-            let t = match id {
-                "openRecentFile" => RecentEntryType::File,
-                "openRecentFolder" => RecentEntryType::Dir,
-                _ => {
-                    eprintln!("Unsupported entry type id!");
-                    return None;
-                }
-            };
-            Some(RecentEntry { t, val })
-        });
-    let uris: Box<dyn Iterator<Item = _>> = match order {
-        RecentOrder::Unchanged => Box::new(uris),
-        RecentOrder::FilesFirst | RecentOrder::DirsFirst => {
-            let (first, second): (Vec<_>, Vec<_>) = uris.partition(|e| {
-                // want_file xnor is_file
-                !((order == RecentOrder::FilesFirst) ^ (e.t == RecentEntryType::File))
-            });
-            Box::new(first.into_iter().chain(second))
+/// Replaces `{placeholder}`s with `value(placeholder)`, or nothing if it returns `None`.
+/// `{{` and `}}` are literal braces.
+fn render_template<'a>(template: &str, value: impl Fn(&str) -> Option<Cow<'a, str>>) -> String {
+    let mut res = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(i) = rest.find(['{', '}']) {
+        res.push_str(&rest[..i]);
+        let c = &rest[i..i + 1];
+        rest = &rest[i + 1..];
+        if let Some(r) = rest.strip_prefix(c) {
+            res.push_str(c);
+            rest = r;
+            continue;
         }
-    };
-    for RecentEntry { val, .. } in uris {
-        let Ok(val) = urlencoding::decode(val).inspect_err(|err| eprintln!("{err}")) else {
+        if c == "}" {
+            res.push('}');
+            continue;
+        }
+        let Some(end) = rest.find('}') else {
+            res.push('{');
             continue;
         };
-        print!(
-            "{}",
-            val.trim()
-                .replace("\t", "")
-                .replace("\n", "")
-                .replace("\0", "")
-        );
-        if null_terminated {
-            print!("\0");
+        if let Some(value) = value(&rest[..end]) {
+            res.push_str(&value);
         }
-        println!();
+        rest = &rest[end + 1..];
     }
-    Ok(())
+    res.push_str(rest);
+    res
 }
 
-#[allow(clippy::too_many_arguments)]
-fn collect_items_in_history(
-    mut storage_path: PathBuf,
-    limit: Option<usize>,
-    max_age_days: Option<u32>,
-    with_dirs: bool,
-    with_remotes: bool,
+struct Output {
     null_terminated: bool,
-    create_display_strings: bool,
     use_pango_markup: bool,
-) -> anyhow::Result<()> {
-    storage_path.push("User/History");
-
-    let min_system_time = max_age_days
-        .map(get_min_system_time_from_max_age_days)
-        .transpose()?;
-
-    let mut entries = fs::read_dir(&storage_path)?
-        .filter_map(|entry| match get_data_from_dir_entry(entry) {
-            Err(err) => {
-                eprintln!("Error at: {}", &storage_path.as_os_str().to_string_lossy());
-                eprintln!("Error reading history entry! {err}");
-                None
-            }
-            Ok(entry) => {
-                if let Some(min_system_time) = min_system_time {
-                    if entry.last_modified_at < min_system_time {
-                        return None;
-                    }
-                }
-                Some(entry)
-            }
-        })
-        .collect::<Vec<_>>();
-
-    entries.sort_by(|e1, e2| e1.last_modified_at.cmp(&e2.last_modified_at).reverse());
-
-    let limit = limit.unwrap_or(usize::MAX);
+    home: Option<PathBuf>,
+    relative_to: Option<PathBuf>,
+    basename_only: bool,
+    remote_hints: bool,
+    template: Option<String>,
+    normalize: bool,
+    timestamp_format: Option<TimestampFormat>,
+    show_source: bool,
+    icons: Option<HashMap<String, String>>,
+    /// Nerd Font glyph overrides, if glyphs should be prepended to display strings.
+    glyphs: Option<HashMap<String, String>>,
+    remote_labels: HashMap<String, String>,
+    /// Printed between the columns of entries with a display string.
+    field_separator: String,
+    /// Applied to the display strings and remote types. Paths already got it.
+    control_chars: ControlChars,
+    /// Printed after each entry instead of a newline.
+    output_separator: Option<String>,
+    /// Wrap the displayed part of entries in ANSI colors.
+    colors: bool,
+    /// Gets the entries instead of printing them as lines, for JSON.
+    json: Option<Json>,
+    /// Written once per entry and flushed by [`Sink::finish`].
+    out: BufWriter<io::Stdout>,
+}
 
-    for FolderEntry { path, .. } in entries.into_iter().take(limit) {
-        let path = path.join("entries.json");
-        if let Err(err) = digest_history_dir_entry(
-            &path,
+impl Output {
+    fn new(format: OutputFormat, null_terminated: bool, use_pango_markup: bool) -> Self {
+        Self {
             null_terminated,
-            with_dirs,
-            with_remotes,
-            create_display_strings,
             use_pango_markup,
-        ) {
-            eprintln!("Error with file: {}", &path.as_os_str().to_string_lossy());
-            eprintln!("Error digesting workspace entry! {err}");
+            home: None,
+            relative_to: None,
+            basename_only: false,
+            remote_hints: true,
+            template: None,
+            normalize: false,
+            timestamp_format: None,
+            show_source: false,
+            icons: None,
+            glyphs: None,
+            remote_labels: HashMap::new(),
+            field_separator: "\t".to_owned(),
+            output_separator: None,
+            colors: false,
+            control_chars: ControlChars::default(),
+            json: (format != OutputFormat::Plain).then(|| Json::new(format)),
+            out: BufWriter::new(io::stdout()),
         }
     }
-    Ok(())
-}
 
-trait SonicRsValueExtensions {
-    type ObjectType;
-    fn as_object_get_result<'a>(&'a self, key: &str) -> anyhow::Result<&'a sonic_rs::Value>;
-    fn as_str_result(&self) -> anyhow::Result<&str>;
-}
+    fn show_timestamps(&mut self, timestamp_format: TimestampFormat) {
+        self.timestamp_format = Some(timestamp_format);
+    }
 
-impl SonicRsValueExtensions for sonic_rs::Value {
-    type ObjectType = sonic_rs::Object;
-    fn as_object_get_result<'a>(&'a self, key: &str) -> anyhow::Result<&'a sonic_rs::Value> {
-        let res = self
-            .as_object()
-            .ok_or_else(|| anyhow!("Failed using field in json as an object!"))?
-            .get(&key)
-            .ok_or_else(|| anyhow!("Failed getting field in json!"))?;
-        Ok(res)
+    fn show_icons(&mut self, icons: HashMap<String, String>) {
+        self.icons = Some(icons);
     }
 
-    fn as_str_result(&self) -> anyhow::Result<&str> {
-        let res = self
-            .as_str()
-            .ok_or_else(|| anyhow!("Failed using field in json as a string!"))?;
-        Ok(res)
+    fn enable_colors(&mut self) {
+        self.colors = true;
     }
-}
 
-#[derive(Clone, Debug)]
-struct DisplayInfo {
-    val: String,
-    hint: Option<DisplayInfoHint>,
-}
+    fn show_glyphs(&mut self, glyphs: HashMap<String, String>) {
+        self.glyphs = Some(glyphs);
+    }
 
-fn print_display_info(val: &DisplayInfo, use_pango_markup: bool) {
-    let DisplayInfo { val, hint } = val;
-    print!("{val}");
-    if let Some(hint) = hint {
-        let DisplayInfoHint {
-            remote_type,
-            addition,
-        } = hint;
-        print!(" ");
-        if use_pango_markup {
-            print!("<small>");
+    fn set_field_separator(&mut self, field_separator: String) {
+        self.field_separator = field_separator;
+    }
+
+    fn set_control_chars(&mut self, control_chars: ControlChars) {
+        self.control_chars = control_chars;
+    }
+
+    fn set_output_separator(&mut self, output_separator: String) {
+        self.output_separator = Some(output_separator);
+    }
+
+    fn set_remote_labels(&mut self, remote_labels: HashMap<String, String>) {
+        self.remote_labels = remote_labels;
+    }
+
+    /// Swaps the built-in label at the start of the hint for the configured one.
+    fn relabel_remote(&self, record: &mut Record) {
+        let Some(remote_type) = record.remote_type.as_deref() else {
+            return;
+        };
+        let Some(label) = self.remote_labels.get(remote_type) else {
+            return;
+        };
+        let Some(hint) = record.display.as_mut().and_then(|d| d.hint.as_mut()) else {
+            return;
+        };
+        let default_label = get_display_string_from_remote_type(remote_type);
+        if let Some(rest) = hint.remote_type.strip_prefix(default_label) {
+            hint.remote_type = format!("{label}{rest}");
         }
-        print!("({remote_type}");
-        if let Some(addition) = addition {
-            print!("|{addition}");
+    }
+
+    fn icon_name<'a>(&'a self, record: &Record) -> Option<&'a str> {
+        let icons = self.icons.as_ref()?;
+        let icon = record
+            .remote_type
+            .as_deref()
+            .and_then(|remote_type| icons.get(remote_type))
+            .or_else(|| icons.get(record.kind.as_str()))
+            .map(String::as_str)
+            .unwrap_or_else(|| get_icon_name(record.kind, record.remote_type.as_deref()));
+        Some(icon)
+    }
+
+    fn glyph<'a>(&'a self, record: &Record) -> Option<&'a str> {
+        let glyphs = self.glyphs.as_ref()?;
+        let glyph = record
+            .remote_type
+            .as_deref()
+            .and_then(|remote_type| glyphs.get(remote_type))
+            .or_else(|| glyphs.get(record.kind.as_str()))
+            .map(String::as_str)
+            .unwrap_or_else(|| get_nerd_glyph(record.kind, record.remote_type.as_deref()));
+        Some(glyph)
+    }
+
+    fn show_source(&mut self) {
+        self.show_source = true;
+    }
+
+    fn enable_normalize(&mut self) {
+        self.normalize = true;
+    }
+
+    fn show_tilde(&mut self, home: PathBuf) {
+        self.home = Some(home);
+    }
+
+    fn show_relative_to(&mut self, base: PathBuf) {
+        self.relative_to = Some(base);
+    }
+
+    fn display_path<'a>(&self, path: &'a str) -> Cow<'a, str> {
+        if let Some(base) = &self.relative_to {
+            match Path::new(path).strip_prefix(base) {
+                Ok(rest) if rest.as_os_str().is_empty() => return Cow::Borrowed("."),
+                Ok(rest) => return Cow::Owned(rest.to_string_lossy().into_owned()),
+                Err(_) => {}
+            }
         }
-        if use_pango_markup {
-            print!("</small>");
+        let Some(home) = &self.home else {
+            return Cow::Borrowed(path);
+        };
+        match Path::new(path).strip_prefix(home) {
+            Ok(rest) if rest.as_os_str().is_empty() => Cow::Borrowed("~"),
+            Ok(rest) => Cow::Owned(format!("~/{}", rest.to_string_lossy())),
+            Err(_) => Cow::Borrowed(path),
+        }
+    }
+
+    fn json_pretty(&mut self) {
+        if let Some(json) = &mut self.json {
+            json.pretty = true;
         }
-        print!(")");
+    }
+
+    fn show_basename_only(&mut self) {
+        self.basename_only = true;
+    }
+
+    fn hide_remote_hints(&mut self) {
+        self.remote_hints = false;
+    }
+
+    fn set_template(&mut self, template: String) {
+        self.template = Some(template);
+    }
+
+    fn render_template(&self, record: &Record) -> String {
+        let Some(template) = &self.template else {
+            return String::new();
+        };
+        let display = match &record.display {
+            Some(display) => Cow::Borrowed(display.val.as_str()),
+            None if record.kind == RecordKind::Remote => Cow::Borrowed(record.path.as_str()),
+            None => self.display_path(&record.path),
+        };
+        let hint = record.display.as_ref().and_then(|d| d.hint.as_ref());
+        let value = |placeholder: &str| -> Option<Cow<'_, str>> {
+            let value = match placeholder {
+                "path" => Cow::Borrowed(record.path.as_str()),
+                "display" => display.clone(),
+                "name" => Cow::Owned(basename(&display).to_owned()),
+                "remote_type" => match hint {
+                    Some(hint) => Cow::Borrowed(hint.remote_type.as_str()),
+                    None => Cow::Borrowed(record.remote_type.as_deref()?),
+                },
+                "addition" => Cow::Borrowed(hint?.addition.as_deref()?),
+                "mtime" => Cow::Owned(format_timestamp(
+                    record.last_modified_at?,
+                    self.timestamp_format.unwrap_or(TimestampFormat::Rfc3339),
+                )),
+                "kind" => Cow::Borrowed(record.kind.as_str()),
+                "source" => Cow::Borrowed(record.source.as_str()),
+                _ => return None,
+            };
+            if self.use_pango_markup {
+                return Some(Cow::Owned(escape_pango_markup(&value).into_owned()));
+            }
+            Some(value)
+        };
+        render_template(template, value)
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-enum RecentEntryType {
-    File,
-    Dir,
+impl Sink for Output {
+    fn emit(&mut self, mut record: Record) -> anyhow::Result<()> {
+        if self.normalize {
+            if let Cow::Owned(path) = normalize_uri(&record.path) {
+                record.path = path;
+            }
+        }
+        self.relabel_remote(&mut record);
+        if record.kind != RecordKind::Remote {
+            // Relative paths and basenames need a separate column to keep the value usable.
+            if (self.relative_to.is_some() || self.basename_only) && record.display.is_none() {
+                record.display = Some(DisplayInfo {
+                    val: record.path.clone(),
+                    hint: None,
+                });
+            }
+            if let Some(display) = &mut record.display {
+                display.val = self.display_path(&display.val).into_owned();
+            }
+        }
+        if self.basename_only {
+            if let Some(display) = &mut record.display {
+                display.val = match &record.workspace {
+                    Some(workspace) => workspace
+                        .file_stem()
+                        .map(|s| s.to_string_lossy().into_owned())
+                        .unwrap_or_default(),
+                    None => basename(&display.val).to_owned(),
+                };
+            }
+        }
+        if let Some(glyph) = self.glyph(&record) {
+            if let Some(display) = &mut record.display {
+                display.val = format!("{glyph} {}", display.val);
+            }
+        }
+        // Templates pick the parts of the hint themselves.
+        if !self.remote_hints && self.template.is_none() {
+            if let Some(display) = &mut record.display {
+                display.hint = None;
+            }
+        }
+        apply_control_chars(&mut record, self.control_chars);
+        if let Some(json) = &mut self.json {
+            return json.emit(record);
+        }
+        if record.display.is_some() && self.template.is_none() {
+            escape_field_separator(&mut record, &self.field_separator);
+        }
+        let separator = &self.field_separator;
+        let color = self.colors.then(|| ansi_color(record.kind));
+        let mut line = String::new();
+        match &record.display {
+            _ if self.template.is_some() => line = self.render_template(&record),
+            Some(display) => {
+                if let Some(icon) = self.icon_name(&record) {
+                    write!(line, "{icon}{separator}")?;
+                }
+                write!(line, "{}{separator}", record.path)?;
+                if let Some(color) = color {
+                    write!(line, "\x1b[{color}m")?;
+                }
+                write_display_info(&mut line, display, self.use_pango_markup)?;
+                if color.is_some() {
+                    line.push_str(ANSI_RESET);
+                }
+                if self.show_source {
+                    write!(line, "{separator}{}", record.source.as_str())?;
+                }
+                if let (Some(timestamp_format), Some(last_modified_at)) =
+                    (self.timestamp_format, record.last_modified_at)
+                {
+                    write!(
+                        line,
+                        "{separator}{}",
+                        format_timestamp(last_modified_at, timestamp_format)
+                    )?;
+                }
+            }
+            None => {
+                let path = match record.kind {
+                    RecordKind::Remote => Cow::Borrowed(record.path.as_str()),
+                    _ => self.display_path(&record.path),
+                };
+                match color {
+                    Some(color) => write!(line, "\x1b[{color}m{path}{ANSI_RESET}")?,
+                    None => line.push_str(&path),
+                }
+            }
+        }
+        match &self.output_separator {
+            _ if self.null_terminated => line.push('\0'),
+            Some(output_separator) => line.push_str(output_separator),
+            None => line.push('\n'),
+        }
+        self.out.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        if let Some(json) = &mut self.json {
+            json.finish()?;
+        }
+        self.out.flush()?;
+        Ok(())
+    }
 }
 
-#[derive(Debug, Clone)]
-struct RecentEntry<'a> {
-    t: RecentEntryType,
-    val: &'a str,
+/// Prints entries as JSON, either one array or one object per line.
+struct Json {
+    lines: bool,
+    pretty: bool,
+    records: Vec<JsonRecord>,
+    out: BufWriter<io::Stdout>,
 }
 
-#[derive(Clone, Debug)]
-struct DisplayInfoHint {
-    remote_type: String,
-    addition: Option<&'static str>,
+impl Json {
+    fn new(format: OutputFormat) -> Self {
+        Self {
+            lines: format == OutputFormat::JsonLines,
+            pretty: false,
+            records: Vec::new(),
+            out: BufWriter::new(io::stdout()),
+        }
+    }
 }
 
-impl Display for DisplayInfoHint {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let DisplayInfoHint {
-            remote_type,
-            addition,
-        } = self;
-        f.write_fmt(format_args!(" ({remote_type}"))?;
-        if let Some(addition) = addition {
-            f.write_fmt(format_args!("|{addition}"))?;
+impl Sink for Json {
+    fn emit(&mut self, record: Record) -> anyhow::Result<()> {
+        if self.lines {
+            let json = sonic_rs::to_string(&JsonRecord::from(record))?;
+            writeln!(self.out, "{json}")?;
+        } else {
+            self.records.push(record.into());
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        if !self.lines {
+            let json = if self.pretty {
+                sonic_rs::to_string_pretty(&self.records)?
+            } else {
+                sonic_rs::to_string(&self.records)?
+            };
+            writeln!(self.out, "{json}")?;
         }
-        f.write_fmt(format_args!(")"))?;
+        self.out.flush()?;
         Ok(())
     }
 }
+
+/// Prints the number of entries instead of the entries.
+#[derive(Default)]
+struct Count(usize);
+
+impl Sink for Count {
+    fn emit(&mut self, _record: Record) -> anyhow::Result<()> {
+        self.0 += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        println!("{}", self.0);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_pango_markup_replaces_special_chars() {
+        assert_eq!(escape_pango_markup("a & b"), "a &amp; b");
+        assert_eq!(escape_pango_markup("<dir>"), "&lt;dir&gt;");
+        assert_eq!(escape_pango_markup("&lt;"), "&amp;lt;");
+    }
+
+    #[test]
+    fn escape_pango_markup_of_path() {
+        assert_eq!(
+            escape_pango_markup("/home/me/A & B/<project>"),
+            "/home/me/A &amp; B/&lt;project&gt;"
+        );
+    }
+
+    #[test]
+    fn escape_pango_markup_borrows_plain_text() {
+        assert!(matches!(
+            escape_pango_markup("/home/me/git/alpha"),
+            Cow::Borrowed("/home/me/git/alpha")
+        ));
+    }
+}
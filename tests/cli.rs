@@ -0,0 +1,2115 @@
+use assert_cmd::Command;
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    sync::Once,
+    time::{Duration, SystemTime},
+};
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// Storage directories of the fixture and their age in days. Git doesn't keep mtimes, so
+/// they are set before the first run.
+const AGES: &[(&str, u64)] = &[
+    ("workspaceStorage/2c3d", 0),
+    ("workspaceStorage/0a1f", 1),
+    ("workspaceStorage/3d4c", 2),
+    ("workspaceStorage/1b2e", 3),
+    ("workspaceStorage/4e5b", 4),
+    ("History/c3d4", 0),
+    ("History/a1b2", 1),
+];
+
+fn fixture() -> PathBuf {
+    static SET_MTIMES: Once = Once::new();
+    let config_root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/Code");
+    SET_MTIMES.call_once(|| {
+        // 2023-11-14T22:13:20Z
+        let newest = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        for (dir, age) in AGES {
+            let dir = config_root.join("User").join(dir);
+            File::open(&dir)
+                .and_then(|dir| dir.set_modified(newest - Duration::from_secs(age * DAY)))
+                .unwrap_or_else(|err| panic!("Failed setting mtime of {}! {err}", dir.display()));
+        }
+    });
+    config_root
+}
+
+fn codep() -> Command {
+    codep_at(&fixture())
+}
+
+fn codep_at(config_root: &Path) -> Command {
+    let mut command = Command::cargo_bin("codep").unwrap();
+    command
+        .env_remove("CODEP_CONFIG_ROOT")
+        .env_remove("CODEP_EDITOR")
+        .env_remove("CODEP_EXECUTABLE")
+        .env_remove("CODEP_FORMAT")
+        .env_remove("RUST_LOG")
+        .env("TZ", "UTC")
+        .arg("--no-config")
+        .arg("--config-root")
+        .arg(config_root);
+    command
+}
+
+/// Fresh config root under the target dir with a workspace storage directory per
+/// `workspace.json` content, the first one newest.
+fn workspaces_at(name: &str, workspaces: &[&str]) -> PathBuf {
+    let config_root = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join(name)
+        .join("Code");
+    let _ = std::fs::remove_dir_all(&config_root);
+    let newest = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    for (i, content) in workspaces.iter().enumerate() {
+        let dir = config_root.join(format!("User/workspaceStorage/{i}"));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("workspace.json"), content).unwrap();
+        File::open(&dir)
+            .and_then(|dir| dir.set_modified(newest - Duration::from_secs(i as u64 * DAY)))
+            .unwrap();
+    }
+    config_root
+}
+
+fn stdout(command: &mut Command) -> String {
+    let output = command.output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn recent_keeps_menu_order() {
+    assert_eq!(
+        stdout(codep().args(["recent", "--all"])),
+        "/home/me/git/alpha\n\
+         /home/me/notes.md\n\
+         /home/me/git/with space\n\
+         /home/me/git/alpha/src/main.rs\n"
+    );
+}
+
+#[test]
+fn recent_selects_kinds() {
+    assert_eq!(
+        stdout(codep().args(["recent", "--with-files"])),
+        "/home/me/notes.md\n/home/me/git/alpha/src/main.rs\n"
+    );
+    assert_eq!(
+        stdout(codep().args(["recent", "--with-dirs"])),
+        "/home/me/git/alpha\n/home/me/git/with space\n"
+    );
+}
+
+#[test]
+fn recent_orders_by_kind() {
+    assert_eq!(
+        stdout(codep().args(["recent", "--all", "--order", "dirs-first"])),
+        "/home/me/git/alpha\n\
+         /home/me/git/with space\n\
+         /home/me/notes.md\n\
+         /home/me/git/alpha/src/main.rs\n"
+    );
+    assert_eq!(
+        stdout(codep().args(["recent", "--all", "--order", "files-first"])),
+        "/home/me/notes.md\n\
+         /home/me/git/alpha/src/main.rs\n\
+         /home/me/git/alpha\n\
+         /home/me/git/with space\n"
+    );
+}
+
+#[test]
+fn workspaces_newest_first() {
+    assert_eq!(
+        stdout(codep().args(["workspaces", "--all"])),
+        "vscode-remote://dev-container+7b22686f737450617468223a222f686f6d652f6d652f6769742f64656c7461227d/workspaces/delta\n\
+         file:///home/me/git/alpha\n\
+         vscode-remote://wsl+Ubuntu/home/me/git/epsilon\n\
+         vscode-remote://ssh-remote+buildbox/home/me/git/gamma\n\
+         file:///home/me/git/with space\n"
+    );
+}
+
+#[test]
+fn workspaces_without_remotes() {
+    assert_eq!(
+        stdout(codep().args(["workspaces", "--with-dirs"])),
+        "file:///home/me/git/alpha\nfile:///home/me/git/with space\n"
+    );
+}
+
+#[test]
+fn workspaces_display_strings() {
+    assert_eq!(
+        stdout(codep().args(["workspaces", "--all", "--create-display-strings"])),
+        "vscode-remote://dev-container+7b22686f737450617468223a222f686f6d652f6d652f6769742f64656c7461227d/workspaces/delta\t/home/me/git/delta (Dev Container)\n\
+         file:///home/me/git/alpha\t/home/me/git/alpha\n\
+         vscode-remote://wsl+Ubuntu/home/me/git/epsilon\t/home/me/git/epsilon (WSL|Ubuntu)\n\
+         vscode-remote://ssh-remote+buildbox/home/me/git/gamma\t/home/me/git/gamma (SSH Remote|buildbox)\n\
+         file:///home/me/git/with space\t/home/me/git/with space\n"
+    );
+}
+
+#[test]
+fn workspaces_sorted_and_reversed() {
+    assert_eq!(
+        stdout(codep().args(["workspaces", "--with-dirs", "--reverse"])),
+        "file:///home/me/git/with space\nfile:///home/me/git/alpha\n"
+    );
+    assert_eq!(
+        stdout(codep().args(["workspaces", "--all", "--sort", "name", "--limit", "2"])),
+        "file:///home/me/git/alpha\n\
+         vscode-remote://dev-container+7b22686f737450617468223a222f686f6d652f6d652f6769742f64656c7461227d/workspaces/delta\n"
+    );
+}
+
+#[test]
+fn history_newest_first() {
+    assert_eq!(
+        stdout(codep().args(["history", "--all"])),
+        "file:///home/me/notes.md\nfile:///home/me/git/alpha/src/main.rs\n"
+    );
+}
+
+#[test]
+fn null_terminated_entries() {
+    assert_eq!(
+        stdout(codep().args(["--null-terminated", "recent", "--with-dirs"])),
+        "/home/me/git/alpha\0/home/me/git/with space\0"
+    );
+}
+
+#[test]
+fn json_pretty_matches_golden_file() {
+    let golden = include_str!("fixtures/history-pretty.json");
+    assert_eq!(
+        stdout(codep().args(["--format", "json", "--json-pretty", "history", "--all"])),
+        golden
+    );
+}
+
+#[test]
+fn json_pretty_rejects_json_lines() {
+    codep()
+        .args(["--format", "json-lines", "--json-pretty", "history"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn portable_install_reads_data_dir() {
+    let install_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/portable");
+    let output = stdout(
+        Command::cargo_bin("codep")
+            .unwrap()
+            .env_remove("CODEP_CONFIG_ROOT")
+            .arg("--no-config")
+            .arg("--portable")
+            .arg(install_dir)
+            .args(["workspaces", "--with-dirs"]),
+    );
+    assert_eq!(output, "file:///home/me/portable/project\n");
+}
+
+#[test]
+fn config_root_from_env() {
+    let output = stdout(
+        Command::cargo_bin("codep")
+            .unwrap()
+            .env("CODEP_CONFIG_ROOT", fixture())
+            .arg("--no-config")
+            .args(["history", "--all"]),
+    );
+    assert_eq!(
+        output,
+        "file:///home/me/notes.md\nfile:///home/me/git/alpha/src/main.rs\n"
+    );
+}
+
+#[test]
+fn only_wins_over_all() {
+    assert_eq!(
+        stdout(codep().args(["--all", "--only", "remotes", "workspaces"])),
+        "vscode-remote://dev-container+7b22686f737450617468223a222f686f6d652f6d652f6769742f64656c7461227d/workspaces/delta\n\
+         vscode-remote://wsl+Ubuntu/home/me/git/epsilon\n\
+         vscode-remote://ssh-remote+buildbox/home/me/git/gamma\n"
+    );
+    assert_eq!(
+        stdout(codep().args(["--only", "files", "recent", "--all"])),
+        "/home/me/notes.md\n/home/me/git/alpha/src/main.rs\n"
+    );
+}
+
+#[test]
+fn cached_workspaces_match_uncached() {
+    let cache_home = Path::new(env!("CARGO_TARGET_TMPDIR")).join("cache");
+    let uncached = stdout(codep().args(["workspaces", "--all"]));
+    for _ in 0..2 {
+        let cached = stdout(codep().env("XDG_CACHE_HOME", &cache_home).args([
+            "--cache",
+            "workspaces",
+            "--all",
+        ]));
+        assert_eq!(cached, uncached);
+    }
+    assert!(cache_home.join("code-pick-recent").is_dir());
+}
+
+#[test]
+fn cached_history_matches_uncached() {
+    let cache_home = Path::new(env!("CARGO_TARGET_TMPDIR")).join("cache-history");
+    let _ = std::fs::remove_dir_all(&cache_home);
+    let uncached = stdout(codep().args(["history", "--all", "-D"]));
+    for _ in 0..2 {
+        let cached = stdout(
+            codep()
+                .env("XDG_CACHE_HOME", &cache_home)
+                .args(["--cache", "history", "--all", "-D"]),
+        );
+        assert_eq!(cached, uncached);
+    }
+    let cache_dir = cache_home.join("code-pick-recent");
+    assert!(std::fs::read_dir(cache_dir).unwrap().any(|entry| {
+        entry
+            .unwrap()
+            .file_name()
+            .to_string_lossy()
+            .starts_with("history-")
+    }));
+}
+
+#[test]
+fn cache_is_dropped_when_storage_changes() {
+    let cache_home = Path::new(env!("CARGO_TARGET_TMPDIR")).join("cache-changes");
+    let _ = std::fs::remove_dir_all(&cache_home);
+    let config_root = workspaces_at("cache-changes", &[r#"{"folder":"file:///home/me/one"}"#]);
+    let cached = |args: &[&str]| {
+        stdout(
+            codep_at(&config_root)
+                .env("XDG_CACHE_HOME", &cache_home)
+                .arg("--cache")
+                .args(args),
+        )
+    };
+    assert_eq!(cached(&["workspaces", "--all"]), "file:///home/me/one\n");
+    // Other options of the same subcommand aren't served from it.
+    assert_eq!(
+        cached(&["workspaces", "--all", "-D"]),
+        "file:///home/me/one\t/home/me/one\n"
+    );
+
+    let dir = config_root.join("User/workspaceStorage/new");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("workspace.json"),
+        r#"{"folder":"file:///home/me/two"}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        cached(&["workspaces", "--all"]),
+        "file:///home/me/two\nfile:///home/me/one\n"
+    );
+
+    // Rewritten in place, which doesn't touch the directory.
+    std::fs::write(
+        dir.join("workspace.json"),
+        r#"{"folder":"file:///home/me/three"}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        cached(&["workspaces", "--all"]),
+        "file:///home/me/three\nfile:///home/me/one\n"
+    );
+}
+
+#[test]
+fn cache_is_dropped_when_code_workspace_file_changes() {
+    let base = Path::new(env!("CARGO_TARGET_TMPDIR")).join("cache-code-workspace");
+    let _ = std::fs::remove_dir_all(&base);
+    let config_root = base.join("Code");
+    let storage_path = config_root.join("User/workspaceStorage/8a9b");
+    std::fs::create_dir_all(&storage_path).unwrap();
+    let workspace_path = base.join("team.code-workspace");
+    std::fs::write(
+        &workspace_path,
+        r#"{"folders":[{"uri":"file:///srv/api"}]}"#,
+    )
+    .unwrap();
+    std::fs::write(
+        storage_path.join("workspace.json"),
+        format!(r#"{{"workspace":"file://{}"}}"#, workspace_path.display()),
+    )
+    .unwrap();
+    let cached = || {
+        stdout(
+            codep_at(&config_root)
+                .env("XDG_CACHE_HOME", base.join("cache"))
+                .args(["--cache", "workspaces", "--all"]),
+        )
+    };
+    assert_eq!(cached(), "file:///srv/api\n");
+
+    // Lives outside of the storage, so only its own mtime tells.
+    std::fs::write(
+        &workspace_path,
+        r#"{"folders":[{"uri":"file:///srv/web"}]}"#,
+    )
+    .unwrap();
+    assert_eq!(cached(), "file:///srv/web\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinked_workspace_storage() {
+    use std::os::unix::fs::symlink;
+    let config_root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("symlinked/Code");
+    let storage_path = config_root.join("User/workspaceStorage");
+    let _ = std::fs::remove_dir_all(&config_root);
+    std::fs::create_dir_all(&storage_path).unwrap();
+    let target = fixture().join("User/workspaceStorage/0a1f");
+    symlink(&target, storage_path.join("0a1f")).unwrap();
+    // Links to files are still skipped.
+    symlink(target.join("workspace.json"), storage_path.join("file")).unwrap();
+
+    let output = stdout(codep_at(&config_root).args(["workspaces", "--all"]));
+    assert_eq!(output, "file:///home/me/git/alpha\n");
+}
+
+#[test]
+fn print_resolved_paths_of_subcommand() {
+    let user_dir = fixture().join("User");
+    assert_eq!(
+        stdout(codep().args(["--print-resolved-paths", "workspaces"])),
+        format!("{}\n", user_dir.join("workspaceStorage").display())
+    );
+    assert_eq!(
+        stdout(codep().arg("--print-config-root")),
+        format!("{}\n", fixture().display())
+    );
+}
+
+#[test]
+fn remote_label_overrides_built_in() {
+    let output = stdout(codep().args([
+        "--remote-label",
+        "ssh-remote=SSH",
+        "--remote-label",
+        "wsl=Linux",
+        "workspaces",
+        "--all",
+        "--create-display-strings",
+    ]));
+    assert!(
+        output.contains("\t/home/me/git/gamma (SSH|buildbox)\n"),
+        "{output}"
+    );
+    assert!(
+        output.contains("\t/home/me/git/epsilon (Linux|Ubuntu)\n"),
+        "{output}"
+    );
+    assert!(
+        output.contains("\t/home/me/git/delta (Dev Container)\n"),
+        "{output}"
+    );
+}
+
+#[test]
+fn tab_in_path_keeps_columns() {
+    let config_root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("tab/Code");
+    let storage_path = config_root.join("User/workspaceStorage/7f8e");
+    std::fs::create_dir_all(&storage_path).unwrap();
+    std::fs::write(
+        storage_path.join("workspace.json"),
+        r#"{"folder":"file:///home/me/tab%09dir"}"#,
+    )
+    .unwrap();
+
+    let output = stdout(codep_at(&config_root).args(["workspaces", "--all", "-D"]));
+    assert_eq!(output, "file:///home/me/tabdir\t/home/me/tabdir\n");
+
+    // Kept control chars still don't break the columns.
+    let output = stdout(codep_at(&config_root).args([
+        "--control-chars",
+        "keep",
+        "workspaces",
+        "--all",
+        "-D",
+    ]));
+    let line = output.lines().next().unwrap();
+    assert_eq!(line.split('\t').count(), 2, "{line}");
+    assert!(line.ends_with("\t/home/me/tab dir"), "{line}");
+
+    let output = stdout(codep_at(&config_root).args([
+        "--control-chars",
+        "keep",
+        "--field-separator",
+        ";",
+        "workspaces",
+        "--all",
+        "-D",
+    ]));
+    assert_eq!(output.lines().next().unwrap().split(';').count(), 2);
+}
+
+#[test]
+fn home_override() {
+    assert_eq!(
+        stdout(codep().args(["--home", "/home/me", "--tilde", "recent", "--with-dirs"])),
+        "~/git/alpha\n~/git/with space\n"
+    );
+    assert_eq!(
+        stdout(codep().args([
+            "--home",
+            "/home/me",
+            "--include",
+            "~/git/**",
+            "recent",
+            "--all"
+        ])),
+        "/home/me/git/alpha\n/home/me/git/with space\n/home/me/git/alpha/src/main.rs\n"
+    );
+}
+
+#[test]
+fn ndjson_is_json_lines() {
+    let output = stdout(codep().args(["--format", "ndjson", "history", "--all"]));
+    assert_eq!(output.lines().count(), 2);
+    assert!(output.starts_with(r#"{"path":"file:///home/me/notes.md","kind":"#));
+}
+
+#[test]
+fn existing_only_keeps_remotes() {
+    assert_eq!(
+        stdout(codep().args(["--existing-only", "workspaces", "--all"])),
+        "vscode-remote://dev-container+7b22686f737450617468223a222f686f6d652f6d652f6769742f64656c7461227d/workspaces/delta\n\
+         vscode-remote://wsl+Ubuntu/home/me/git/epsilon\n\
+         vscode-remote://ssh-remote+buildbox/home/me/git/gamma\n"
+    );
+}
+
+#[test]
+fn config_root_wins_over_variant() {
+    assert_eq!(
+        stdout(codep().args(["--variant", "codium", "--print-config-root"])),
+        format!("{}\n", fixture().display())
+    );
+}
+
+#[test]
+fn tilde_only_in_display_column() {
+    assert_eq!(
+        stdout(codep().args([
+            "--home",
+            "/home/me",
+            "--tilde",
+            "workspaces",
+            "--with-dirs",
+            "--create-display-strings"
+        ])),
+        "file:///home/me/git/alpha\t~/git/alpha\n\
+         file:///home/me/git/with space\t~/git/with space\n"
+    );
+    // Only whole path components are collapsed.
+    assert_eq!(
+        stdout(codep().args(["--home", "/home/m", "--tilde", "recent", "--with-dirs"])),
+        "/home/me/git/alpha\n/home/me/git/with space\n"
+    );
+}
+
+#[test]
+fn pango_markup_is_escaped() {
+    let config_root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("pango/Code");
+    let storage_path = config_root.join("User/workspaceStorage");
+    for (dir, folder) in [
+        ("8a9b", "file:///home/me/A%20%26%20B/%3Cproject%3E"),
+        ("9b8a", "vscode-remote://ssh-remote%2Bme%26co/home/me/x"),
+    ] {
+        std::fs::create_dir_all(storage_path.join(dir)).unwrap();
+        std::fs::write(
+            storage_path.join(dir).join("workspace.json"),
+            format!(r#"{{"folder":"{folder}"}}"#),
+        )
+        .unwrap();
+    }
+
+    let output = stdout(codep_at(&config_root).args(["-p", "workspaces", "--all", "-D"]));
+    assert!(
+        output.contains("\t/home/me/A &amp; B/&lt;project&gt;\n"),
+        "{output}"
+    );
+    assert!(
+        output.contains("\t/home/me/x <small>(SSH Remote|me&amp;co)</small>\n"),
+        "{output}"
+    );
+}
+
+#[test]
+fn remote_hex_decodes_utf8() {
+    let config_root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("utf8/Code");
+    let storage_path = config_root.join("User/workspaceStorage/6c7d");
+    std::fs::create_dir_all(&storage_path).unwrap();
+    // {"hostPath":"/home/me/café"}
+    std::fs::write(
+        storage_path.join("workspace.json"),
+        r#"{"folder":"vscode-remote://dev-container%2B7b22686f737450617468223a222f686f6d652f6d652f636166c3a9227d/workspaces/cafe"}"#,
+    )
+    .unwrap();
+
+    let output = stdout(codep_at(&config_root).args(["workspaces", "--all", "-D"]));
+    assert!(
+        output.ends_with("\t/home/me/café (Dev Container)\n"),
+        "{output}"
+    );
+}
+
+#[test]
+fn recent_limit_after_order() {
+    assert_eq!(
+        stdout(codep().args(["recent", "--all", "--order", "files-first", "--limit", "3"])),
+        "/home/me/notes.md\n/home/me/git/alpha/src/main.rs\n/home/me/git/alpha\n"
+    );
+}
+
+#[test]
+fn all_merges_sources_newest_first() {
+    assert_eq!(
+        stdout(codep().args(["all", "--all"])),
+        "vscode-remote://dev-container+7b22686f737450617468223a222f686f6d652f6d652f6769742f64656c7461227d/workspaces/delta\n\
+         file:///home/me/notes.md\n\
+         file:///home/me/git/alpha\n\
+         file:///home/me/git/alpha/src/main.rs\n\
+         vscode-remote://wsl+Ubuntu/home/me/git/epsilon\n\
+         vscode-remote://ssh-remote+buildbox/home/me/git/gamma\n\
+         file:///home/me/git/with space\n"
+    );
+}
+
+#[test]
+fn timestamp_column_comes_last() {
+    assert_eq!(
+        stdout(codep().args([
+            "history",
+            "--all",
+            "-D",
+            "--with-timestamp",
+            "--timestamp-format",
+            "epoch"
+        ])),
+        "file:///home/me/notes.md\t/home/me/notes.md\t1700000000\n\
+         file:///home/me/git/alpha/src/main.rs\t/home/me/git/alpha/src/main.rs\t1699913600\n"
+    );
+}
+
+#[test]
+fn relative_time_counts_back_from_now() {
+    let out = stdout(codep().args(["history", "--all", "-D", "--relative-time"]));
+    assert_eq!(out.lines().count(), 2);
+    for line in out.lines() {
+        assert!(line.ends_with(" weeks ago"), "{line}");
+    }
+}
+
+#[test]
+fn completions_dont_need_a_config_root() {
+    let script = stdout(codep_at(Path::new("/nonexistent")).args(["completions", "zsh"]));
+    assert!(script.starts_with("#compdef codep\n"), "{script}");
+    assert!(script.contains("workspaces"));
+}
+
+#[cfg(unix)]
+#[test]
+fn open_passes_decoded_paths_and_remote_uris() {
+    let open = |target: &str| stdout(codep().args(["open", "--executable", "echo", target]));
+    assert_eq!(
+        open("file:///home/me/git/with space"),
+        "--goto /home/me/git/with space\n"
+    );
+    // Paths aren't decoded twice.
+    assert_eq!(open("file:///home/me/100%41"), "--goto /home/me/100%41\n");
+    assert_eq!(
+        open("vscode-remote://ssh-remote+buildbox/home/me/git/gamma"),
+        "--folder-uri vscode-remote://ssh-remote%2Bbuildbox/home/me/git/gamma\n"
+    );
+    assert_eq!(
+        open("vscode-remote://ssh-remote+buildbox/home/me/50% off/a b"),
+        "--folder-uri vscode-remote://ssh-remote%2Bbuildbox/home/me/50%25%20off/a%20b\n"
+    );
+}
+
+#[test]
+fn open_remote_history_entries_as_files() {
+    let config_root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("open-remote/Code");
+    let history_path = config_root.join("User/History/e5f6");
+    let _ = std::fs::remove_dir_all(&config_root);
+    std::fs::create_dir_all(&history_path).unwrap();
+    std::fs::write(
+        history_path.join("entries.json"),
+        r#"{"version":1,"resource":"vscode-remote://ssh-remote%2Bbuildbox/home/me/todo.md","entries":[]}"#,
+    )
+    .unwrap();
+
+    let target = stdout(codep_at(&config_root).args(["history", "--with-remotes"]));
+    assert_eq!(
+        target,
+        "vscode-remote://ssh-remote+buildbox/home/me/todo.md\n"
+    );
+    assert_eq!(
+        stdout(codep_at(&config_root).args(["open", "--executable", "echo", target.trim_end()])),
+        "--file-uri vscode-remote://ssh-remote%2Bbuildbox/home/me/todo.md\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn open_exits_with_the_editors_code() {
+    codep()
+        .args(["open", "--executable", "false", "/tmp"])
+        .assert()
+        .code(1)
+        .stderr("");
+}
+
+#[test]
+fn code_workspace_folders_are_deduplicated() {
+    let config_root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("multi-root/Code");
+    let storage_path = config_root.join("User/workspaceStorage/8a9b");
+    std::fs::create_dir_all(&storage_path).unwrap();
+    let workspace_path = config_root.join("team.code-workspace");
+    std::fs::write(
+        &workspace_path,
+        r#"{"folders":[{"path":"api"},{"path":"./api/"},{"uri":"file:///srv/web"}]}"#,
+    )
+    .unwrap();
+    std::fs::write(
+        storage_path.join("workspace.json"),
+        format!(r#"{{"workspace":"file://{}"}}"#, workspace_path.display()),
+    )
+    .unwrap();
+
+    let output = stdout(codep_at(&config_root).args(["workspaces", "--all", "-D"]));
+    assert_eq!(
+        output,
+        format!(
+            "file://{api}\t{api} (Workspace|team)\nfile:///srv/web\t/srv/web (Workspace|team)\n",
+            api = config_root.join("api").display()
+        )
+    );
+    assert_eq!(
+        stdout(codep_at(&config_root).args(["--basename", "workspaces", "--all", "-D"])),
+        format!(
+            "file://{api}\tteam (Workspace|team)\nfile:///srv/web\tteam (Workspace|team)\n",
+            api = config_root.join("api").display()
+        )
+    );
+}
+
+#[test]
+fn ssh_host_is_shown_as_addition() {
+    let config_root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("ssh/Code");
+    let storage_path = config_root.join("User/workspaceStorage");
+    for (dir, folder) in [
+        // myhost
+        ("a1a1", "vscode-remote://ssh-remote%2B6d79686f7374/home/me"),
+        // me@otherhost
+        (
+            "b2b2",
+            "vscode-remote://ssh-remote%2B6d65406f74686572686f7374/srv",
+        ),
+        // {"hostName":"jsonhost"}
+        (
+            "c3c3",
+            "vscode-remote://ssh-remote%2B7b22686f73744e616d65223a226a736f6e686f7374227d/opt",
+        ),
+    ] {
+        std::fs::create_dir_all(storage_path.join(dir)).unwrap();
+        std::fs::write(
+            storage_path.join(dir).join("workspace.json"),
+            format!(r#"{{"folder":"{folder}"}}"#),
+        )
+        .unwrap();
+    }
+
+    let output =
+        stdout(codep_at(&config_root).args(["workspaces", "--all", "-D", "--sort", "path"]));
+    let displays: Vec<_> = output
+        .lines()
+        .map(|line| line.split_once('\t').unwrap().1)
+        .collect();
+    assert_eq!(
+        displays,
+        [
+            "/home/me (SSH Remote|myhost)",
+            "/opt (SSH Remote|jsonhost)",
+            "/srv (SSH Remote|otherhost)"
+        ]
+    );
+}
+
+#[test]
+fn wsl_distro_is_shown_as_addition() {
+    let config_root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("wsl/Code");
+    let storage_path = config_root.join("User/workspaceStorage/d4d4");
+    std::fs::create_dir_all(&storage_path).unwrap();
+    // Ubuntu-22.04
+    std::fs::write(
+        storage_path.join("workspace.json"),
+        r#"{"folder":"vscode-remote://wsl%2B5562756e74752d32322e3034/home/me"}"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        stdout(codep_at(&config_root).args(["workspaces", "--all", "-D"])),
+        "vscode-remote://wsl+5562756e74752d32322e3034/home/me\t/home/me (WSL|Ubuntu-22.04)\n"
+    );
+}
+
+#[test]
+fn match_any_string_by_mode() {
+    assert_eq!(
+        stdout(codep().args([
+            "--match",
+            "alpha",
+            "--match",
+            "epsilon",
+            "workspaces",
+            "--all"
+        ])),
+        "file:///home/me/git/alpha\n\
+         vscode-remote://wsl+Ubuntu/home/me/git/epsilon\n"
+    );
+    assert_eq!(
+        stdout(codep().args([
+            "--match",
+            "/home/me/git/alpha",
+            "--match-mode",
+            "exact",
+            "all",
+            "--all"
+        ])),
+        "file:///home/me/git/alpha\n"
+    );
+    assert_eq!(
+        stdout(codep().args([
+            "--home",
+            "/home/me",
+            "--match",
+            "~/git",
+            "--match-mode",
+            "prefix",
+            "recent",
+            "--all"
+        ])),
+        "/home/me/git/alpha\n/home/me/git/with space\n/home/me/git/alpha/src/main.rs\n"
+    );
+}
+
+#[test]
+fn filter_regexes_have_to_match_all() {
+    assert_eq!(
+        stdout(codep().args([
+            "--filter-regex",
+            "git",
+            "--filter-regex",
+            "a$",
+            "--exclude-regex",
+            "delta",
+            "workspaces",
+            "--all"
+        ])),
+        "file:///home/me/git/alpha\n\
+         vscode-remote://ssh-remote+buildbox/home/me/git/gamma\n"
+    );
+    let output = codep()
+        .args(["--filter-regex", "(", "recent"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid value '('"));
+}
+
+#[test]
+fn output_separator_replaces_newline() {
+    assert_eq!(
+        stdout(codep().args(["--output-separator", r"\t", "recent", "--with-dirs"])),
+        "/home/me/git/alpha\t/home/me/git/with space\t"
+    );
+    codep()
+        .args(["-0", "--output-separator", ";", "recent"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn control_chars_in_paths() {
+    let config_root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("newline/Code");
+    let storage_path = config_root.join("User/workspaceStorage/7f8e");
+    std::fs::create_dir_all(&storage_path).unwrap();
+    std::fs::write(
+        storage_path.join("workspace.json"),
+        r#"{"folder":"file:///home/me/new%0Aline"}"#,
+    )
+    .unwrap();
+
+    let paths = |mode| {
+        stdout(codep_at(&config_root).args(["--control-chars", mode, "workspaces", "--all"]))
+    };
+    assert_eq!(paths("strip"), "file:///home/me/newline\n");
+    assert_eq!(paths("escape"), "file:///home/me/new\\nline\n");
+    assert_eq!(paths("keep"), "file:///home/me/new\nline\n");
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn recent_entries_from_state_db() {
+    let config_root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("state-db/Code");
+    let global_storage = config_root.join("User/globalStorage");
+    std::fs::create_dir_all(&global_storage).unwrap();
+    let db_path = global_storage.join("state.vscdb");
+    let _ = std::fs::remove_file(&db_path);
+    let connection = rusqlite::Connection::open(&db_path).unwrap();
+    connection
+        .execute_batch(
+            r#"CREATE TABLE ItemTable (key TEXT UNIQUE ON CONFLICT REPLACE, value BLOB);
+            INSERT INTO ItemTable VALUES ('history.recentlyOpenedPathsList',
+                '{"entries":[{"folderUri":"file:///home/me/git/zeta"},{"fileUri":"file:///home/me/todo.md"}]}');"#,
+        )
+        .unwrap();
+    drop(connection);
+
+    assert_eq!(
+        stdout(codep_at(&config_root).args(["recent", "--all"])),
+        "/home/me/git/zeta\n/home/me/todo.md\n"
+    );
+}
+
+#[test]
+fn recently_opened_list_beats_menubar() {
+    let config_root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("recently-opened/Code");
+    let global_storage = config_root.join("User/globalStorage");
+    std::fs::create_dir_all(&global_storage).unwrap();
+    let menubar =
+        std::fs::read_to_string(fixture().join("User/globalStorage/storage.json")).unwrap();
+    let storage = menubar.trim_end().strip_suffix('}').unwrap().to_owned()
+        + r#", "history.recentlyOpenedPathsList": {"entries": [
+            {"folderUri": "file:///home/me/git/zeta"},
+            {"fileUri": "file:///home/me/todo.md"}
+        ]}}"#;
+    std::fs::write(global_storage.join("storage.json"), storage).unwrap();
+
+    assert_eq!(
+        stdout(codep_at(&config_root).args(["recent", "--all"])),
+        "/home/me/git/zeta\n/home/me/todo.md\n"
+    );
+}
+
+#[test]
+fn global_sort_applies_after_collecting() {
+    assert_eq!(
+        stdout(codep().args([
+            "--sort",
+            "mtime-asc",
+            "--first-only",
+            "2",
+            "workspaces",
+            "--all"
+        ])),
+        "file:///home/me/git/with space\n\
+         vscode-remote://ssh-remote+buildbox/home/me/git/gamma\n"
+    );
+    assert_eq!(
+        stdout(codep().args(["--sort", "alpha", "recent", "--all"])),
+        "/home/me/git/alpha\n\
+         /home/me/git/alpha/src/main.rs\n\
+         /home/me/git/with space\n\
+         /home/me/notes.md\n"
+    );
+    // Uris are sorted by the path they show, not by scheme and authority.
+    let output = stdout(codep().args(["--sort", "alpha", "workspaces", "--all", "-D"]));
+    let shown = output
+        .lines()
+        .map(|line| line.split('\t').nth(1).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        shown,
+        [
+            "/home/me/git/alpha",
+            "/home/me/git/delta (Dev Container)",
+            "/home/me/git/epsilon (WSL|Ubuntu)",
+            "/home/me/git/gamma (SSH Remote|buildbox)",
+            "/home/me/git/with space",
+        ]
+    );
+    assert_eq!(
+        stdout(codep().args(["--sort", "path-length", "workspaces", "--all"]))
+            .lines()
+            .next(),
+        Some(
+            "vscode-remote://dev-container+7b22686f737450617468223a222f686f6d652f6d652f6769742f64656c7461227d/workspaces/delta"
+        )
+    );
+    // Recent entries have no mtime to sort by.
+    assert_eq!(
+        stdout(codep().args(["--sort", "mtime-asc", "recent", "--all"])),
+        stdout(codep().args(["recent", "--all"]))
+    );
+}
+
+#[test]
+fn count_matches_listing() {
+    for args in [
+        &["workspaces", "--with-remotes"][..],
+        &["workspaces", "--all", "-M", "2"],
+        &["history", "--all", "--limit", "1"],
+        &["recent", "--with-dirs"],
+    ] {
+        let listed = stdout(codep().args(args)).lines().count();
+        let counted = stdout(codep().arg("count").args(args));
+        assert_eq!(counted, format!("{listed}\n"), "{args:?}");
+    }
+}
+
+#[test]
+fn limit_keeps_the_newest_directories() {
+    for subcommand in ["workspaces", "history"] {
+        let all = stdout(codep().args([subcommand, "--all"]));
+        for limit in 0..=5 {
+            let limited =
+                stdout(codep().args([subcommand, "--all", "--limit", &limit.to_string()]));
+            let expected: String = all
+                .lines()
+                .take(limit)
+                .map(|line| format!("{line}\n"))
+                .collect();
+            assert_eq!(limited, expected, "{subcommand} --limit {limit}");
+        }
+    }
+}
+
+#[test]
+fn no_cache_beats_cache() {
+    let cache_home = Path::new(env!("CARGO_TARGET_TMPDIR")).join("no-cache");
+    let _ = std::fs::remove_dir_all(&cache_home);
+    stdout(
+        codep()
+            .env("XDG_CACHE_HOME", &cache_home)
+            .env("CODEP_CACHE", "1")
+            .args(["--no-cache", "workspaces", "--all"]),
+    );
+    assert!(!cache_home.exists());
+}
+
+// Backslashes are always used on Windows.
+#[cfg(unix)]
+#[test]
+fn windows_drive_letter_uris() {
+    let config_root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("windows/Code");
+    let storage_path = config_root.join("User/workspaceStorage");
+    for (dir, folder) in [
+        ("e5e5", "file:///c%3A/Users/me/project"),
+        ("f6f6", "file:///D:/src/other%20project"),
+    ] {
+        std::fs::create_dir_all(storage_path.join(dir)).unwrap();
+        std::fs::write(
+            storage_path.join(dir).join("workspace.json"),
+            format!(r#"{{"folder":"{folder}"}}"#),
+        )
+        .unwrap();
+    }
+
+    let displays = |args: &[&str]| {
+        let output = stdout(codep_at(&config_root).args(args).args([
+            "workspaces",
+            "--all",
+            "-D",
+            "--sort",
+            "path",
+        ]));
+        output
+            .lines()
+            .map(|line| line.split_once('\t').unwrap().1.to_owned())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        displays(&[]),
+        ["D:/src/other project", "c:/Users/me/project"]
+    );
+    assert_eq!(
+        displays(&["--windows-paths"]),
+        [r"D:\src\other project", r"c:\Users\me\project"]
+    );
+}
+
+#[test]
+fn example_config_keeps_fileserver_share() {
+    let config_root = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/example_code_config");
+    let output = stdout(codep_at(&config_root).args(["workspaces", "--all", "-D"]));
+    assert!(
+        output.contains(
+            "file://fileserver/share/projects/code-pick-rs\t//fileserver/share/projects/code-pick-rs (Network|fileserver)\n"
+        ),
+        "{output}"
+    );
+}
+
+#[test]
+fn network_share_shows_host() {
+    let config_root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("unc/Code");
+    let storage_path = config_root.join("User/workspaceStorage");
+    for (dir, folder) in [
+        ("a7a7", "file://server/share/project"),
+        ("b8b8", "file://localhost/home/me/local"),
+    ] {
+        std::fs::create_dir_all(storage_path.join(dir)).unwrap();
+        std::fs::write(
+            storage_path.join(dir).join("workspace.json"),
+            format!(r#"{{"folder":"{folder}"}}"#),
+        )
+        .unwrap();
+    }
+
+    let output = stdout(codep_at(&config_root).args(["workspaces", "--all", "-D"]));
+    assert!(
+        output.contains("file://server/share/project\t//server/share/project (Network|server)\n"),
+        "{output}"
+    );
+    assert!(
+        output.contains("file://localhost/home/me/local\t/home/me/local\n"),
+        "{output}"
+    );
+}
+
+#[test]
+fn basename_keeps_value_column() {
+    assert_eq!(
+        stdout(codep().args(["--basename", "recent", "--all"])),
+        "/home/me/git/alpha\talpha\n\
+         /home/me/notes.md\tnotes.md\n\
+         /home/me/git/with space\twith space\n\
+         /home/me/git/alpha/src/main.rs\tmain.rs\n"
+    );
+    let output = stdout(codep().args(["--basename-only", "workspaces", "--all", "-D"]));
+    assert!(
+        output.contains("\tgamma (SSH Remote|buildbox)\n"),
+        "{output}"
+    );
+}
+
+#[test]
+fn icons_prepend_glyphs_to_display_strings() {
+    let output = stdout(codep().args(["--icons", "workspaces", "--all", "-D"]));
+    assert!(
+        output.contains("file:///home/me/git/alpha\t\u{f07b} /home/me/git/alpha\n"),
+        "{output}"
+    );
+    assert!(
+        output.contains("\t\u{f120} /home/me/git/gamma (SSH Remote|buildbox)\n"),
+        "{output}"
+    );
+    assert!(output.contains("\t\u{f308} /home/me/git/delta"), "{output}");
+}
+
+#[test]
+fn color_wraps_displayed_part() {
+    assert_eq!(
+        stdout(codep().args(["--color", "always", "recent", "--all"])),
+        "\x1b[1;34m/home/me/git/alpha\x1b[0m\n\
+         \x1b[32m/home/me/notes.md\x1b[0m\n\
+         \x1b[1;34m/home/me/git/with space\x1b[0m\n\
+         \x1b[32m/home/me/git/alpha/src/main.rs\x1b[0m\n"
+    );
+    let output = stdout(codep().args(["--color", "always", "workspaces", "--all", "-D"]));
+    assert!(
+        output.contains("file:///home/me/git/alpha\t\x1b[1;34m/home/me/git/alpha\x1b[0m\n"),
+        "{output}"
+    );
+    // Not a terminal.
+    assert!(!stdout(codep().args(["recent", "--all"])).contains('\x1b'));
+    assert!(
+        !codep()
+            .args(["--color", "always", "-p", "recent"])
+            .output()
+            .unwrap()
+            .status
+            .success()
+    );
+}
+
+#[test]
+fn reverse_flips_final_order() {
+    assert_eq!(
+        stdout(codep().args(["--reverse", "recent", "--all"])),
+        "/home/me/git/alpha/src/main.rs\n\
+         /home/me/git/with space\n\
+         /home/me/notes.md\n\
+         /home/me/git/alpha\n"
+    );
+    assert_eq!(
+        stdout(codep().args(["--reverse", "--first-only", "1", "workspaces", "--all"])),
+        "file:///home/me/git/with space\n"
+    );
+    assert_eq!(
+        stdout(codep().args(["--sort", "alpha", "--reverse", "recent", "--all"])),
+        stdout(codep().args(["--sort", "alpha-desc", "recent", "--all"]))
+    );
+}
+
+#[test]
+fn config_roots_are_merged() {
+    let portable =
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/portable/data/user-data");
+    let output = stdout(
+        codep()
+            .arg("--config-root")
+            .arg(portable)
+            .args(["workspaces", "--with-dirs"]),
+    );
+    // The portable fixture keeps the mtime of the checkout, which is newer.
+    assert_eq!(
+        output,
+        "file:///home/me/portable/project\n\
+         file:///home/me/git/alpha\n\
+         file:///home/me/git/with space\n"
+    );
+    // Duplicates across roots are dropped.
+    let twice = format!("{},{}", fixture().display(), fixture().display());
+    let output = stdout(
+        Command::cargo_bin("codep")
+            .unwrap()
+            .env("CODEP_CONFIG_ROOT", twice)
+            .arg("--no-config")
+            .args(["history", "--all"]),
+    );
+    assert_eq!(
+        output,
+        "file:///home/me/notes.md
+file:///home/me/git/alpha/src/main.rs
+"
+    );
+}
+
+#[test]
+fn unreadable_workspace_files_are_summed_up() {
+    let config_root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("corrupt/Code");
+    let storage_path = config_root.join("User/workspaceStorage");
+    let _ = std::fs::remove_dir_all(&config_root);
+    for (dir, content) in [
+        ("good", r#"{"folder":"file:///home/me/good"}"#),
+        ("bad1", r#"{"folder":"file:///home"#),
+        ("bad2", "not json"),
+    ] {
+        std::fs::create_dir_all(storage_path.join(dir)).unwrap();
+        std::fs::write(storage_path.join(dir).join("workspace.json"), content).unwrap();
+    }
+
+    let output = codep_at(&config_root)
+        .args(["workspaces", "--all"])
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "file:///home/me/good\n"
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr, "warn: Skipped 2 unreadable workspace files!\n");
+
+    let output = codep_at(&config_root)
+        .args(["-v", "workspaces", "--all"])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr.matches("Error reading").count(), 2, "{stderr}");
+    assert!(!stderr.contains("Skipped"), "{stderr}");
+}
+
+#[test]
+fn very_verbose_reports_files_read() {
+    let output = codep().args(["-vv", "recent", "--all"]).output().unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let storage_path = fixture().join("User/globalStorage/storage.json");
+    assert!(
+        stderr.contains(&format!("trace: Reading {}\n", storage_path.display())),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains(&format!(
+            "trace: 4 entries from {}\n",
+            storage_path.display()
+        )),
+        "{stderr}"
+    );
+    let output = codep().args(["-v", "recent", "--all"]).output().unwrap();
+    assert!(!String::from_utf8(output.stderr).unwrap().contains("trace:"));
+}
+
+#[test]
+fn since_and_until_intersect_with_after() {
+    assert_eq!(
+        stdout(codep().args([
+            "--since",
+            "2023-11-11",
+            "--until",
+            "2023-11-14",
+            "workspaces",
+            "--all"
+        ])),
+        "file:///home/me/git/alpha\n\
+         vscode-remote://wsl+Ubuntu/home/me/git/epsilon\n\
+         vscode-remote://ssh-remote+buildbox/home/me/git/gamma\n"
+    );
+    assert_eq!(
+        stdout(codep().args([
+            "--since",
+            "2023-11-11",
+            "workspaces",
+            "--all",
+            "--after",
+            "2023-11-13",
+        ])),
+        "vscode-remote://dev-container+7b22686f737450617468223a222f686f6d652f6d652f6769742f64656c7461227d/workspaces/delta\n\
+         file:///home/me/git/alpha\n"
+    );
+    let output = codep()
+        .args([
+            "--since",
+            "2023-11-14",
+            "--until",
+            "2023-11-01",
+            "workspaces",
+        ])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("`--since` is later than `--until`")
+    );
+}
+
+#[test]
+fn history_keeps_newest_entry_of_a_file() {
+    let config_root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("duplicates/Code");
+    let history_path = config_root.join("User/History");
+    let _ = std::fs::remove_dir_all(&config_root);
+    let newest = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    for (dir, resource, age) in [
+        ("old", "file:///home/me/a.rs", 2),
+        ("other", "file:///home/me/b.rs", 1),
+        ("new", "file:///home/me/a.rs", 0),
+    ] {
+        let dir = history_path.join(dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("entries.json"),
+            format!(r#"{{"version":1,"resource":"{resource}","entries":[]}}"#),
+        )
+        .unwrap();
+        File::open(&dir)
+            .and_then(|dir| dir.set_modified(newest - Duration::from_secs(age * DAY)))
+            .unwrap();
+    }
+
+    let args = [
+        "history",
+        "-D",
+        "--with-timestamp",
+        "--timestamp-epoch",
+        "--all",
+    ];
+    assert_eq!(
+        stdout(codep_at(&config_root).args(args)),
+        "file:///home/me/a.rs\t/home/me/a.rs\t1700000000\n\
+         file:///home/me/b.rs\t/home/me/b.rs\t1699913600\n"
+    );
+    for no_dedupe in ["--no-dedupe", "--no-dedup"] {
+        assert_eq!(
+            stdout(codep_at(&config_root).args(args).arg(no_dedupe))
+                .lines()
+                .count(),
+            3
+        );
+    }
+    codep_at(&config_root)
+        .args(args)
+        .args(["--no-dedupe", "--dedupe"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn history_with_files() {
+    let output = stdout(codep().args(["history", "--with-files"]));
+    assert_eq!(
+        output,
+        "file:///home/me/notes.md\nfile:///home/me/git/alpha/src/main.rs\n"
+    );
+    assert_eq!(stdout(codep().args(["history", "--with-dirs"])), output);
+    assert_eq!(
+        stdout(codep().args(["--only", "files", "history", "--all"])),
+        output
+    );
+    assert_eq!(
+        stdout(codep().args(["--only", "dirs", "history", "--all"])),
+        ""
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn default_config_root_falls_back_to_dot_config() {
+    let tmp = Path::new(env!("CARGO_TARGET_TMPDIR")).join("fallback");
+    let _ = std::fs::remove_dir_all(&tmp);
+    let xdg_config_home = tmp.join("xdg");
+    let config_root = tmp.join("home/.config/Code");
+    std::fs::create_dir_all(&xdg_config_home).unwrap();
+    std::fs::create_dir_all(config_root.join("User/globalStorage")).unwrap();
+    std::fs::write(config_root.join("User/globalStorage/storage.json"), "{}").unwrap();
+
+    let output = Command::cargo_bin("codep")
+        .unwrap()
+        .env_remove("CODEP_CONFIG_ROOT")
+        .env_remove("CODEP_EDITOR")
+        .env("XDG_CONFIG_HOME", &xdg_config_home)
+        .env("HOME", tmp.join("home"))
+        .args(["--no-config", "-v", "--print-config-root"])
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("{}\n", config_root.display())
+    );
+    assert!(String::from_utf8(output.stderr).unwrap().contains(&format!(
+        "debug: Using config root {}\n",
+        config_root.display()
+    )));
+}
+
+#[test]
+fn null_terminated_display_strings_end_in_a_single_nul() {
+    let output = codep()
+        .args(["-0", "workspaces", "--with-dirs", "-D"])
+        .output()
+        .unwrap();
+    assert_eq!(
+        output.stdout,
+        b"file:///home/me/git/alpha\t/home/me/git/alpha\0\
+          file:///home/me/git/with space\t/home/me/git/with space\0"
+    );
+}
+
+#[test]
+fn codespaces_and_tunnels_keep_plain_names() {
+    let config_root = workspaces_at(
+        "plain-names",
+        &[
+            r#"{"folder":"vscode-remote://codespaces%2Bfuzzy-space-goggles-4x5r7q9/workspaces/code-pick-rs"}"#,
+            r#"{"folder":"vscode-remote://tunnel%2Bmy-desktop/home/me/git/code-pick-rs"}"#,
+            r#"{"folder":"vscode-remote://tunnel%2Bb%C3%BCro/home/x"}"#,
+            r#"{"folder":"vscode-remote://tunnel%2Bcafe/home/y"}"#,
+            r#"{"folder":"vscode-remote://codespaces%2Bdeadbeef/workspaces/z"}"#,
+        ],
+    );
+    let output = stdout(codep_at(&config_root).args(["workspaces", "--all", "-D"]));
+    let displays: Vec<_> = output
+        .lines()
+        .map(|line| line.split_once('\t').unwrap().1)
+        .collect();
+    assert_eq!(
+        displays,
+        [
+            "/workspaces/code-pick-rs (Codespaces|fuzzy-space-goggles-4x5r7q9)",
+            "/home/me/git/code-pick-rs (Tunnel|my-desktop)",
+            "/home/x (Tunnel|büro)",
+            "/home/y (Tunnel|cafe)",
+            "/workspaces/z (Codespaces|deadbeef)",
+        ]
+    );
+}
+
+#[test]
+fn control_chars_in_display_column() {
+    let config_root = workspaces_at(
+        "display-control-chars",
+        &[r#"{"folder":"file:///home/me/new%0Aline"}"#],
+    );
+    assert_eq!(
+        stdout(codep_at(&config_root).args(["workspaces", "--all", "-D"])),
+        "file:///home/me/newline\t/home/me/newline\n"
+    );
+    assert_eq!(
+        stdout(codep_at(&config_root).args([
+            "--control-chars",
+            "escape",
+            "workspaces",
+            "--all",
+            "-D"
+        ])),
+        "file:///home/me/new\\nline\t/home/me/new\\nline\n"
+    );
+    assert_eq!(
+        stdout(codep_at(&config_root).args([
+            "--control-chars",
+            "escape",
+            "--template",
+            "{display}",
+            "workspaces",
+            "--all",
+            "-D"
+        ])),
+        "/home/me/new\\nline\n"
+    );
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn corrupt_state_db_falls_back_to_storage_json() {
+    let config_root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("corrupt-state-db/Code");
+    let global_storage = config_root.join("User/globalStorage");
+    let _ = std::fs::remove_dir_all(&config_root);
+    std::fs::create_dir_all(&global_storage).unwrap();
+    std::fs::copy(
+        fixture().join("User/globalStorage/storage.json"),
+        global_storage.join("storage.json"),
+    )
+    .unwrap();
+    std::fs::write(global_storage.join("state.vscdb"), "garbage").unwrap();
+
+    let output = codep_at(&config_root)
+        .args(["recent", "--all"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        stdout(codep().args(["recent", "--all"]))
+    );
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("falling back to storage.json")
+    );
+}
+
+/// Storage for an existing and a missing local folder, a remote, a network share and a
+/// broken `workspace.json`, in that order.
+fn clean_fixture(name: &str) -> PathBuf {
+    let existing = format!(r#"{{"folder":"file://{}"}}"#, env!("CARGO_MANIFEST_DIR"));
+    workspaces_at(
+        name,
+        &[
+            &existing,
+            r#"{"folder":"file:///nonexistent/codep/missing"}"#,
+            r#"{"folder":"vscode-remote://ssh-remote%2Bbuildbox/nonexistent"}"#,
+            r#"{"folder":"file://fileserver/share/nonexistent"}"#,
+            "{",
+        ],
+    )
+}
+
+fn storage_dirs(config_root: &Path) -> Vec<String> {
+    let mut dirs = std::fs::read_dir(config_root.join("User/workspaceStorage"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    dirs.sort();
+    dirs
+}
+
+#[test]
+fn clean_dry_run_keeps_storage() {
+    let config_root = clean_fixture("clean-dry-run");
+    let output = stdout(codep_at(&config_root).args(["clean", "--dry-run"]));
+    let storage_path = config_root.join("User/workspaceStorage");
+    assert!(
+        output.starts_with(&format!("{}\t", storage_path.join("1").display())),
+        "{output}"
+    );
+    assert_eq!(output.lines().count(), 2, "{output}");
+    assert!(
+        output.ends_with(" B\n") && output.contains("Would reclaim "),
+        "{output}"
+    );
+    assert_eq!(storage_dirs(&config_root), ["0", "1", "2", "3", "4"]);
+}
+
+#[test]
+fn clean_removes_missing_local_targets() {
+    let config_root = clean_fixture("clean-missing");
+    let output = stdout(codep_at(&config_root).arg("clean"));
+    assert!(output.contains("Reclaimed "), "{output}");
+    assert_eq!(storage_dirs(&config_root), ["0", "2", "3", "4"]);
+}
+
+#[test]
+fn clean_expires_old_storage() {
+    let config_root = clean_fixture("clean-max-age");
+    File::open(config_root.join("User/workspaceStorage/0"))
+        .and_then(|dir| dir.set_modified(SystemTime::now()))
+        .unwrap();
+    stdout(codep_at(&config_root).args(["clean", "--max-age-days", "30"]));
+    // Remotes, network shares and unreadable storage are kept however old they are.
+    assert_eq!(storage_dirs(&config_root), ["0", "2", "3", "4"]);
+
+    let config_root = clean_fixture("clean-max-age");
+    stdout(codep_at(&config_root).args(["clean", "--max-age-days", "30"]));
+    assert_eq!(storage_dirs(&config_root), ["2", "3", "4"]);
+}
+
+/// Like [`codep_at`], but with `config` as the config file next to the config root and
+/// without a default config file.
+fn codep_with_config(config_root: &Path, config: &str) -> Command {
+    let dir = config_root.parent().unwrap();
+    std::fs::write(dir.join("code-pick-recent.toml"), config).unwrap();
+    let mut command = Command::cargo_bin("codep").unwrap();
+    command
+        .env_remove("CODEP_CONFIG_ROOT")
+        .env_remove("CODEP_EDITOR")
+        .env_remove("CODEP_EXECUTABLE")
+        .env_remove("CODEP_FORMAT")
+        .env_remove("RUST_LOG")
+        .env("XDG_CONFIG_HOME", dir.join("xdg"))
+        .env("TZ", "UTC")
+        .arg("--config-root")
+        .arg(config_root);
+    command
+}
+
+fn zeta_and_alpha(name: &str) -> PathBuf {
+    workspaces_at(
+        name,
+        &[
+            r#"{"folder":"file:///home/me/zeta"}"#,
+            r#"{"folder":"file:///home/me/alpha"}"#,
+        ],
+    )
+}
+
+#[test]
+fn config_next_to_config_root_sets_defaults() {
+    let config_root = zeta_and_alpha("config-next-to-root");
+    let config = "[workspaces]\nsort = \"name\"\ncreate_display_strings = true\n";
+    assert_eq!(
+        stdout(codep_with_config(&config_root, config).args(["workspaces", "--all"])),
+        "file:///home/me/alpha\t/home/me/alpha\nfile:///home/me/zeta\t/home/me/zeta\n"
+    );
+    // The command line beats the file.
+    assert_eq!(
+        stdout(codep_with_config(&config_root, config).args([
+            "workspaces",
+            "--all",
+            "--sort",
+            "mtime"
+        ])),
+        "file:///home/me/zeta\t/home/me/zeta\nfile:///home/me/alpha\t/home/me/alpha\n"
+    );
+    assert_eq!(
+        stdout(codep_with_config(&config_root, config).args([
+            "--no-config",
+            "workspaces",
+            "--all"
+        ])),
+        "file:///home/me/zeta\nfile:///home/me/alpha\n"
+    );
+}
+
+#[test]
+fn env_beats_config_file() {
+    let config_root = zeta_and_alpha("config-env");
+    let config = "format = \"json-lines\"\n";
+    let output = stdout(codep_with_config(&config_root, config).args(["workspaces", "--all"]));
+    assert!(
+        output.starts_with(r#"{"path":"file:///home/me/zeta""#),
+        "{output}"
+    );
+    assert_eq!(
+        stdout(
+            codep_with_config(&config_root, config)
+                .env("CODEP_FORMAT", "plain")
+                .args(["workspaces", "--all"])
+        ),
+        "file:///home/me/zeta\nfile:///home/me/alpha\n"
+    );
+}
+
+#[test]
+fn default_config_file_without_one_next_to_config_root() {
+    let config_root = zeta_and_alpha("config-xdg");
+    let xdg = config_root.parent().unwrap().join("xdg/code-pick-recent");
+    std::fs::create_dir_all(&xdg).unwrap();
+    std::fs::write(xdg.join("config.toml"), "[workspaces]\nsort = \"name\"\n").unwrap();
+    let mut command = codep_with_config(&config_root, "");
+    std::fs::remove_file(config_root.parent().unwrap().join("code-pick-recent.toml")).unwrap();
+    assert_eq!(
+        stdout(command.args(["workspaces", "--all"])),
+        "file:///home/me/alpha\nfile:///home/me/zeta\n"
+    );
+}
+
+#[test]
+fn unknown_history_option_in_config_fails() {
+    let config_root = zeta_and_alpha("config-unknown");
+    let output = codep_with_config(&config_root, "[history]\nwith_dir = true\n")
+        .arg("history")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("unknown field `with_dir`")
+    );
+}
+
+// Elsewhere the config dir doesn't follow `XDG_CONFIG_HOME`.
+#[cfg(target_os = "linux")]
+#[test]
+fn editor_picks_default_config_root() {
+    let base = Path::new(env!("CARGO_TARGET_TMPDIR")).join("editors");
+    for (editor, dir_name) in [
+        ("code", "Code"),
+        ("code-insiders", "Code - Insiders"),
+        ("codium", "VSCodium"),
+        ("oss", "Code - OSS"),
+        ("cursor", "Cursor"),
+    ] {
+        let output = stdout(
+            Command::cargo_bin("codep")
+                .unwrap()
+                .env_remove("CODEP_CONFIG_ROOT")
+                .env_remove("CODEP_EDITOR")
+                .env("HOME", &base)
+                .env("XDG_CONFIG_HOME", base.join("xdg"))
+                .args(["--no-config", "--print-config-root", "--editor", editor]),
+        );
+        assert_eq!(
+            output,
+            format!("{}\n", base.join("xdg").join(dir_name).display())
+        );
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn editor_env_beats_config_file() {
+    let base = Path::new(env!("CARGO_TARGET_TMPDIR")).join("editor-env");
+    let config_dir = base.join("xdg/code-pick-recent");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(config_dir.join("config.toml"), "editor = \"codium\"\n").unwrap();
+    let print_config_root = |editor: Option<&str>| {
+        let mut command = Command::cargo_bin("codep").unwrap();
+        command
+            .env_remove("CODEP_CONFIG_ROOT")
+            .env_remove("CODEP_EDITOR")
+            .env("HOME", &base)
+            .env("XDG_CONFIG_HOME", base.join("xdg"))
+            .arg("--print-config-root");
+        if let Some(editor) = editor {
+            command.env("CODEP_EDITOR", editor);
+        }
+        stdout(&mut command)
+    };
+    let config_root = |dir_name: &str| format!("{}\n", base.join("xdg").join(dir_name).display());
+    assert_eq!(print_config_root(None), config_root("VSCodium"));
+    assert_eq!(
+        print_config_root(Some("code-insiders")),
+        config_root("Code - Insiders")
+    );
+}
+
+#[test]
+fn format_env() {
+    let output = stdout(
+        codep()
+            .env("CODEP_FORMAT", "json")
+            .args(["history", "--all"]),
+    );
+    assert!(output.starts_with(r#"[{"path":"#), "{output}");
+    // The command line beats the environment.
+    assert_eq!(
+        stdout(
+            codep()
+                .env("CODEP_FORMAT", "json")
+                .args(["--format", "plain", "history", "--all"])
+        ),
+        stdout(codep().args(["history", "--all"]))
+    );
+}
+
+#[test]
+fn since_and_until_bound_all() {
+    assert_eq!(
+        stdout(codep().args([
+            "--since",
+            "2023-11-12T00:00:00Z",
+            "--until",
+            "2023-11-14T00:00:00Z",
+            "all",
+            "--all",
+        ])),
+        "file:///home/me/git/alpha\n\
+         file:///home/me/git/alpha/src/main.rs\n\
+         vscode-remote://wsl+Ubuntu/home/me/git/epsilon\n"
+    );
+}
+
+#[test]
+fn frecency_weighs_frequency_against_age() {
+    let config_root = Path::new(env!("CARGO_TARGET_TMPDIR")).join("frecency/Code");
+    let history_path = config_root.join("User/History");
+    let _ = std::fs::remove_dir_all(&config_root);
+    let now = SystemTime::now();
+    for (dir, resource, age) in [
+        ("a1", "file:///home/me/a.rs", 10),
+        ("a2", "file:///home/me/a.rs", 11),
+        ("a3", "file:///home/me/a.rs", 12),
+        ("b", "file:///home/me/b.rs", 0),
+    ] {
+        let dir = history_path.join(dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("entries.json"),
+            format!(r#"{{"version":1,"resource":"{resource}","entries":[]}}"#),
+        )
+        .unwrap();
+        File::open(&dir)
+            .and_then(|dir| dir.set_modified(now - Duration::from_secs(age * DAY)))
+            .unwrap();
+    }
+
+    let history =
+        |args: &[&str]| stdout(codep_at(&config_root).args(["history", "--all"]).args(args));
+    assert_eq!(history(&[]), "file:///home/me/b.rs\nfile:///home/me/a.rs\n");
+    // Three entries ten days ago beat one today when age hardly matters...
+    assert_eq!(
+        history(&["--order", "frecency", "--frecency-halflife-days", "1000"]),
+        "file:///home/me/a.rs\nfile:///home/me/b.rs\n"
+    );
+    // ...but not when the weight halves every day.
+    assert_eq!(
+        history(&["--order", "frecency", "--frecency-halflife-days", "1"]),
+        "file:///home/me/b.rs\nfile:///home/me/a.rs\n"
+    );
+}
+
+#[test]
+fn template_placeholders() {
+    let template = |template: &str| {
+        stdout(codep().args([
+            "--match",
+            "gamma",
+            "--template",
+            template,
+            "workspaces",
+            "--all",
+            "-D",
+        ]))
+    };
+    assert_eq!(
+        template("{name}|{display}|{remote_type}|{addition}|{kind}|{source}|{mtime}"),
+        "gamma|/home/me/git/gamma|SSH Remote|buildbox|remote|workspace|2023-11-11T22:13:20+00:00\n"
+    );
+    assert_eq!(
+        template("{path}"),
+        "vscode-remote://ssh-remote+buildbox/home/me/git/gamma\n"
+    );
+    // Doubled braces are literal, unknown placeholders are left out.
+    assert_eq!(template("{{name}} }}{bogus}-{name}"), "{name} }-gamma\n");
+}
+
+#[test]
+fn placeholders_are_skipped_without_all() {
+    let config_root = workspaces_at(
+        "placeholders",
+        &[
+            r#"{"folder":"file:///home/me/real"}"#,
+            r#"{"folder":"file://"}"#,
+            r#"{"folder":"file:///%20%20"}"#,
+            r#"{"folder":"file:///Untitled"}"#,
+            r#"{"folder":"vscode-remote://ssh-remote%2Bbox/Untitled%20(Workspace)"}"#,
+        ],
+    );
+    assert_eq!(
+        stdout(codep_at(&config_root).args(["workspaces", "--with-dirs", "--with-remotes"])),
+        "file:///home/me/real\n"
+    );
+    assert_eq!(
+        stdout(codep_at(&config_root).args(["workspaces", "--all"]))
+            .lines()
+            .count(),
+        5
+    );
+}
+
+#[test]
+fn stats_in_plain_and_json() {
+    let existing = format!(r#"{{"folder":"file://{}"}}"#, env!("CARGO_MANIFEST_DIR"));
+    let config_root = workspaces_at(
+        "stats",
+        &[
+            &existing,
+            r#"{"folder":"file:///nonexistent/codep/missing"}"#,
+            r#"{"folder":"vscode-remote://ssh-remote%2Bbuildbox/srv"}"#,
+            r#"{"folder":"vscode-remote://wsl%2BUbuntu/home/me"}"#,
+        ],
+    );
+    assert_eq!(
+        stdout(codep_at(&config_root).arg("stats")),
+        "workspace storage dirs\t4\n\
+         workspace entries\t4\n\
+         history entries\t0\n\
+         recent entries\t0\n\
+         existing local paths\t1\n\
+         missing local paths\t1\n\
+         remote ssh-remote\t1\n\
+         remote wsl\t1\n"
+    );
+    assert_eq!(
+        stdout(codep_at(&config_root).args(["--format", "json", "stats"])),
+        r#"{"workspace_storage_dirs":4,"workspace_entries":4,"history_entries":0,"recent_entries":0,"existing":1,"missing":1,"remote_types":{"ssh-remote":1,"wsl":1}}"#
+            .to_owned()
+            + "\n"
+    );
+}
+
+#[test]
+fn profile_reads_its_own_storage() {
+    let config_root = workspaces_at("profiles", &[r#"{"folder":"file:///home/me/default"}"#]);
+    let user_dir = config_root.join("User");
+    std::fs::create_dir_all(user_dir.join("globalStorage")).unwrap();
+    std::fs::write(
+        user_dir.join("globalStorage/storage.json"),
+        r#"{"userDataProfiles":[{"name":"Work","location":"5f3a"}]}"#,
+    )
+    .unwrap();
+    let storage_path = user_dir.join("profiles/5f3a/workspaceStorage/0");
+    std::fs::create_dir_all(&storage_path).unwrap();
+    std::fs::write(
+        storage_path.join("workspace.json"),
+        r#"{"folder":"file:///home/me/work"}"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        stdout(codep_at(&config_root).args(["workspaces", "--all"])),
+        "file:///home/me/default\n"
+    );
+    assert_eq!(
+        stdout(codep_at(&config_root).args(["--profile", "Work", "workspaces", "--all"])),
+        "file:///home/me/work\n"
+    );
+    let output = codep_at(&config_root)
+        .args(["--profile", "Play", "workspaces"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("Unknown profile `Play`! Available: Work")
+    );
+}
+
+#[test]
+fn relative_to_shortens_paths_below_it() {
+    assert_eq!(
+        stdout(codep().args(["--relative-to", "/home/me/git", "recent", "--all"])),
+        "/home/me/git/alpha\talpha\n\
+         /home/me/notes.md\t/home/me/notes.md\n\
+         /home/me/git/with space\twith space\n\
+         /home/me/git/alpha/src/main.rs\talpha/src/main.rs\n"
+    );
+    assert_eq!(
+        stdout(codep().args([
+            "--home",
+            "/home/me",
+            "--relative-to",
+            "~/git",
+            "recent",
+            "--all"
+        ])),
+        stdout(codep().args(["--relative-to", "/home/me/git", "recent", "--all"]))
+    );
+    // The base itself is `.`, remotes keep their paths.
+    let output = stdout(codep().args([
+        "--relative-to",
+        "/home/me/git/alpha",
+        "workspaces",
+        "--all",
+        "-D",
+    ]));
+    assert!(
+        output.contains("file:///home/me/git/alpha\t.\n"),
+        "{output}"
+    );
+    assert!(
+        output.contains("\t/home/me/git/epsilon (WSL|Ubuntu)\n"),
+        "{output}"
+    );
+}
+
+#[test]
+fn skip_missing_drops_missing_local_paths() {
+    let existing = format!("file://{}", env!("CARGO_MANIFEST_DIR"));
+    let config_root = workspaces_at(
+        "skip-missing",
+        &[
+            &format!(r#"{{"folder":"{existing}"}}"#),
+            r#"{"folder":"file:///nonexistent/codep/missing"}"#,
+            r#"{"folder":"vscode-remote://ssh-remote%2Bbuildbox/nonexistent"}"#,
+        ],
+    );
+    std::fs::create_dir_all(config_root.join("User/globalStorage")).unwrap();
+    std::fs::write(
+        config_root.join("User/globalStorage/storage.json"),
+        format!(
+            r#"{{"history.recentlyOpenedPathsList":{{"entries":[
+                {{"folderUri":"{existing}"}},
+                {{"folderUri":"file:///nonexistent/codep/missing"}}
+            ]}}}}"#
+        ),
+    )
+    .unwrap();
+
+    assert_eq!(
+        stdout(codep_at(&config_root).args(["--skip-missing", "recent", "--all"])),
+        format!("{}\n", env!("CARGO_MANIFEST_DIR"))
+    );
+    // Remotes can't be checked and are kept.
+    assert_eq!(
+        stdout(codep_at(&config_root).args(["--skip-missing", "workspaces", "--all"])),
+        format!("{existing}\nvscode-remote://ssh-remote+buildbox/nonexistent\n")
+    );
+    // Existence is checked before the paths are converted.
+    for command in ["recent", "workspaces"] {
+        let output = stdout(codep_at(&config_root).args([
+            "--skip-missing",
+            "--windows-paths",
+            command,
+            "--with-dirs",
+        ]));
+        assert_eq!(output.lines().count(), 1, "{output}");
+    }
+}
+
+/// The same folder twice, once with a trailing slash, and another one with one.
+fn trailing_slashes(name: &str) -> PathBuf {
+    workspaces_at(
+        name,
+        &[
+            r#"{"folder":"file:///home/me/a/"}"#,
+            r#"{"folder":"file:///home/me/a"}"#,
+            r#"{"folder":"file:///home/me/b/"}"#,
+        ],
+    )
+}
+
+#[test]
+fn unique_prints_every_location_once() {
+    let config_root = trailing_slashes("unique");
+    assert_eq!(
+        stdout(codep_at(&config_root).args(["workspaces", "--all"])),
+        "file:///home/me/a/\nfile:///home/me/a\nfile:///home/me/b/\n"
+    );
+    assert_eq!(
+        stdout(codep_at(&config_root).args(["--unique", "workspaces", "--all"])),
+        "file:///home/me/a/\nfile:///home/me/b/\n"
+    );
+    // Duplicates don't count towards `--first-only`.
+    assert_eq!(
+        stdout(codep_at(&config_root).args([
+            "--unique",
+            "--first-only",
+            "2",
+            "workspaces",
+            "--all"
+        ])),
+        "file:///home/me/a/\nfile:///home/me/b/\n"
+    );
+}
+
+#[test]
+fn normalize_trims_trailing_slashes() {
+    let config_root = trailing_slashes("normalize");
+    assert_eq!(
+        stdout(codep_at(&config_root).args(["--normalize", "workspaces", "--all"])),
+        "file:///home/me/a\nfile:///home/me/a\nfile:///home/me/b\n"
+    );
+    // Roots keep their slash.
+    let config_root = workspaces_at("normalize-root", &[r#"{"folder":"file:///"}"#]);
+    assert_eq!(
+        stdout(codep_at(&config_root).args(["--normalize", "workspaces", "--all"])),
+        "file:///\n"
+    );
+}
+
+#[test]
+fn count_only_prints_the_number_of_entries() {
+    assert_eq!(
+        stdout(codep().args(["--count-only", "workspaces", "--all"])),
+        "5\n"
+    );
+    // Counted after dropping duplicates and in every format.
+    let config_root = trailing_slashes("count-only");
+    assert_eq!(
+        stdout(codep_at(&config_root).args([
+            "--count-only",
+            "--unique",
+            "--format",
+            "json",
+            "workspaces",
+            "--all"
+        ])),
+        "2\n"
+    );
+    assert_eq!(
+        stdout(codep().args(["--count-only", "--first-only", "1", "history", "--all"])),
+        "1\n"
+    );
+    assert_eq!(
+        stdout(codep().args(["count", "recent", "--all"])),
+        stdout(codep().args(["--count-only", "recent", "--all"]))
+    );
+}
+
+#[test]
+fn with_icon_prepends_icon_names() {
+    let output = stdout(codep().args(["--with-icon", "history", "--all", "-D"]));
+    assert_eq!(
+        output,
+        "text-x-generic\tfile:///home/me/notes.md\t/home/me/notes.md\n\
+         text-x-generic\tfile:///home/me/git/alpha/src/main.rs\t/home/me/git/alpha/src/main.rs\n"
+    );
+    // Only entries with a display string get a column.
+    assert_eq!(
+        stdout(codep().args(["--with-icon", "history", "--all"])),
+        stdout(codep().args(["history", "--all"]))
+    );
+
+    let config_root = workspaces_at(
+        "with-icon",
+        &[
+            r#"{"folder":"file:///home/me/local"}"#,
+            r#"{"folder":"vscode-remote://ssh-remote%2Bbox/srv"}"#,
+            r#"{"folder":"vscode-remote://wsl%2BUbuntu/home/me"}"#,
+        ],
+    );
+    let config = "[icons]\ndir = \"folder-open\"\nwsl = \"tux\"\n";
+    assert_eq!(
+        stdout(codep_with_config(&config_root, config).args([
+            "--with-icon",
+            "workspaces",
+            "--all",
+            "-D"
+        ])),
+        "folder-open\tfile:///home/me/local\t/home/me/local\n\
+         folder-remote\tvscode-remote://ssh-remote+box/srv\t/srv (SSH Remote|box)\n\
+         tux\tvscode-remote://wsl+Ubuntu/home/me\t/home/me (WSL|Ubuntu)\n"
+    );
+}